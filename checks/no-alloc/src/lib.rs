@@ -0,0 +1,32 @@
+//! Uses the XMODEM API the way a target without an allocator would, so that
+//! `cargo check --manifest-path checks/no-alloc/Cargo.toml` fails if any of
+//! it comes to need `alloc`.
+#![no_std]
+#![deny(warnings, missing_docs)]
+
+use core2::io::{Read, Write};
+use txmodems::variants::xmodem::{
+    ChecksumKind, ModemResult, ModemTrait, XModem, XModemTrait,
+};
+
+/// Sends `data` from a slice.
+pub fn send<D: Read + Write>(dev: &mut D, data: &[u8]) -> ModemResult<()> {
+    XModem::new().send_slice(dev, data)
+}
+
+/// Sends `inp` through a caller-provided block buffer.
+pub fn send_with_buffer<D: Read + Write, R: Read>(
+    dev: &mut D,
+    inp: &mut R,
+    buf: &mut [u8],
+) -> ModemResult<()> {
+    XModem::new().send_with_buffer(dev, inp, buf)
+}
+
+/// Receives into a fixed buffer, returning the number of bytes received.
+pub fn recv<D: Read + Write>(
+    dev: &mut D,
+    buf: &mut [u8],
+) -> ModemResult<usize> {
+    XModem::crc().recv_into(dev, buf, ChecksumKind::Crc16)
+}
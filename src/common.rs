@@ -1,66 +1,507 @@
-#![allow(dead_code)]
+// Only XMODEM is implemented so far, so without it most of this module has
+// no users yet.
+#![cfg_attr(not(feature = "xmodem"), allow(dead_code))]
 
-extern crate alloc;
-
-use alloc::boxed::Box;
+#[cfg(feature = "alloc")]
 use alloc::string::String;
 
 use anyhow::Result;
+use core::hash::Hasher;
 use core2::io::{Error, Read, Write};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use thiserror_no_std::Error;
 pub use utils::*;
 
-#[derive(Default, Copy, Clone, Debug)]
+/// The checksum used to validate each block.
+///
+/// With the `serde` feature, this is (de)serialized as `"standard"`,
+/// `"crc16"` or `"crc16-ccitt"`.
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum ChecksumKind {
+    /// A single-byte additive checksum.
     #[default]
     Standard,
+    /// A two-byte CRC-16 (XMODEM polynomial).
+    ///
+    /// Like every XMODEM CRC, it is sent most significant byte first
+    /// (big-endian), as real XMODEM implementations expect.
+    #[cfg_attr(feature = "serde", serde(rename = "crc16"))]
     Crc16,
+    /// A two-byte CRC-16/CCITT-FALSE, which uses the same polynomial as
+    /// `Crc16` but an initial value of `0xFFFF`.
+    ///
+    /// Some devices sold as "XMODEM-CRC" compute this instead of the XMODEM
+    /// CRC. The two can't be told apart during negotiation, so if a device
+    /// rejects every block in CRC mode, it probably needs this variant.
+    #[cfg_attr(feature = "serde", serde(rename = "crc16-ccitt"))]
+    Crc16Ccitt,
 }
 
-#[derive(Default, Copy, Clone, Debug)]
+/// The length of the data portion of each block.
+///
+/// With the `serde` feature, this is (de)serialized as `"standard"` or
+/// `"1k"`.
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum BlockLengthKind {
+    /// 128-byte blocks (standard XMODEM).
     #[default]
+    #[cfg_attr(feature = "serde", serde(rename = "standard"))]
     Standard = 128,
+    /// 1024-byte blocks (XMODEM-1k).
+    #[cfg_attr(feature = "serde", serde(rename = "1k"))]
     OneK = 1024,
 }
 
+/// How the sender fills the rest of the last block, when the data doesn't
+/// fill it.
+///
+/// XMODEM doesn't transmit the length of the data, so the receiver can't tell
+/// padding from data. `Byte(0x1a)` is what most senders do, and what most
+/// receivers expect: on CP/M a `^Z` marked the end of a text file, so text
+/// comes out right, but binary files gain up to a block of trailing `0x1a`s.
+/// `CtrlZTerminated` instead writes a single `^Z` after the data and zeroes
+/// the rest, so a receiver can cut the data at the last `^Z`. Receivers that
+/// trim trailing `0x1a`s (see `recv_trim_pad`) will not trim the zeroes, so
+/// only use it when the receiver is known to look for the terminator.
+///
+/// With the `serde` feature, this is (de)serialized as `{ "byte": 26 }` or
+/// `"ctrl-z-terminated"`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PadPolicy {
+    /// Fill the rest of the block with this byte.
+    Byte(u8),
+    /// Write a `^Z` (`0x1a`) after the data and fill the rest with `0x00`.
+    /// If the data fills its last block exactly, there is no room for the
+    /// `^Z` and none is sent.
+    CtrlZTerminated,
+}
+
+impl Default for PadPolicy {
+    fn default() -> Self {
+        Self::Byte(0x1a)
+    }
+}
+
+impl PadPolicy {
+    /// Pads `block`, whose first `n` bytes are data.
+    pub(crate) fn pad(self, block: &mut [u8], n: usize) {
+        let rest = &mut block[n..];
+        match self {
+            Self::Byte(b) => rest.fill(b),
+            Self::CtrlZTerminated => {
+                rest.fill(0x00);
+                if let Some(first) = rest.first_mut() {
+                    *first = 0x1a;
+                }
+            }
+        }
+    }
+}
+
+impl TryFrom<usize> for BlockLengthKind {
+    type Error = ModemError;
+
+    fn try_from(length: usize) -> Result<Self, Self::Error> {
+        match length {
+            128 => Ok(Self::Standard),
+            1024 => Ok(Self::OneK),
+            length => Err(ModemError::UnsupportedBlockLength { length }),
+        }
+    }
+}
+
+impl TryFrom<u16> for BlockLengthKind {
+    type Error = ModemError;
+
+    fn try_from(length: u16) -> Result<Self, Self::Error> {
+        Self::try_from(usize::from(length))
+    }
+}
+
 /// Enum of various `Error` variants.
+///
+/// New variants may be added in minor releases, so `match`es on this enum
+/// need a wildcard arm.
 #[derive(Debug, Error)]
+#[non_exhaustive]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum ModemError {
-    /// Boxed `core2::io::Error`, used for storing I/O errors.
+    /// The underlying `core2::io::Error`, stored inline.
     #[error("Error during I/O on the channel.")]
-    Io(#[from] Error),
+    Io(
+        #[from]
+        #[cfg_attr(feature = "defmt", defmt(Debug2Format))]
+        Error,
+    ),
 
     /// The number of communications errors exceeded `max_errors` in a single
     /// transmission.
     #[error("Too many errors, aborting - max errors: {errors}")]
-    ExhaustedRetries { errors: Box<u32> },
+    ExhaustedRetries {
+        /// The number of errors encountered.
+        errors: u32,
+    },
+
+    /// The number of communications errors exceeded `max_errors` while
+    /// waiting for a specific byte, and `got` was the last byte received
+    /// instead.
+    #[error(
+        "Too many errors, aborting - expected {} ({expected:#04x}), got {} ({got:#04x})",
+        control_byte_name(*.expected),
+        control_byte_name(*.got)
+    )]
+    UnexpectedByte {
+        /// The last byte received.
+        got: u8,
+        /// The byte that was expected.
+        expected: u8,
+    },
 
     /// The transmission was canceled by the other end of the channel.
     #[error("Cancelled by the other party.")]
     Canceled,
+
+    /// Every error counted against `max_errors` (or `max_initial_errors`)
+    /// was a timeout: the other party never answered, rather than the line
+    /// being noisy.
+    #[error("Timed out waiting for the other party.")]
+    Timeout,
+
+    /// A YMODEM header block could not be decoded, e.g. because its file
+    /// size field was empty or not a number.
+    #[error("Invalid YMODEM header.")]
+    InvalidHeader,
+
+    /// The data didn't match the checksum sent with it.
+    #[error("The data did not match its checksum.")]
+    ChecksumMismatch,
+
+    /// The sender sent more data than `max_recv_bytes` allows, or than fits
+    /// in the buffer passed to `recv_into`.
+    #[error("Received more than the limit of {limit} bytes.")]
+    SizeLimitExceeded {
+        /// The limit that was exceeded.
+        limit: u64,
+    },
+
+    /// `max_unrecognized` bytes in a row were neither a block header nor a
+    /// control byte, which usually means the two ends disagree on the baud
+    /// rate or the line is too noisy to use.
+    #[error("Line error, got {unexpected:#04x} where a header was expected.")]
+    LineError {
+        /// The last of the unrecognized bytes.
+        unexpected: u8,
+    },
+
+    /// The protocol chosen for a `Modem` is not implemented yet.
+    #[error("The protocol is not implemented yet.")]
+    UnsupportedProtocol,
+
+    /// A block length other than 128 or 1024 bytes was requested.
+    #[error("Unsupported block length: {length}")]
+    UnsupportedBlockLength {
+        /// The requested length.
+        length: usize,
+    },
+}
+
+/// Protocol events reported while a transfer is in progress.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ModemEvent {
+    /// A block was sent. Blocks are numbered from the start of the transfer,
+    /// not by their wrapping on-the-wire number.
+    BlockSent {
+        /// The number of the block.
+        num: u32,
+    },
+    /// A block was acknowledged, either by the other party when sending or by
+    /// us when receiving.
+    BlockAcked {
+        /// The number of the block.
+        num: u32,
+    },
+    /// A block was rejected with a `NAK`.
+    Nak,
+    /// Timed out waiting for a byte.
+    Timeout,
+    /// The transfer was canceled.
+    Canceled,
+    /// A response was waiting before a block was sent, so it can't be for
+    /// that block (e.g. a late `NAK` for the previous one), or the receiver
+    /// repeated its request to start the transfer. It is discarded.
+    StaleResponse {
+        /// The byte received.
+        byte: u8,
+    },
+    /// The receiver didn't answer `SILENT_EOT_RETRIES` `EOT`s in a row, so
+    /// the send was taken to be complete, its `ACK` of an earlier `EOT`
+    /// having most likely been lost. It may also have gone away, which the
+    /// sender can't tell apart.
+    EotUnacknowledged,
+}
+
+/// Counts of what happened during a transfer, to judge the quality of the
+/// link. These are gathered even when the transfer succeeds.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TransferStats {
+    /// The number of blocks acknowledged.
+    pub blocks: u32,
+    /// The number of blocks sent again: resent by us when sending, or
+    /// received twice when receiving.
+    pub retransmits: u32,
+    /// The number of times a read timed out.
+    pub timeouts: u32,
+    /// The number of blocks rejected with a `NAK`, usually because their
+    /// checksum didn't match.
+    pub crc_failures: u32,
+    /// Whether the send finished without the receiver acknowledging the
+    /// `EOT`, as it stopped answering. See `ModemEvent::EotUnacknowledged`.
+    pub eot_unacknowledged: bool,
+}
+
+/// The number of a block, as sent on the wire: it starts at 1 and wraps
+/// from 255 back to 0.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PacketNumber(pub u8);
+
+impl PacketNumber {
+    /// The one's complement of the number, sent after it in a block header.
+    pub const fn complement(self) -> u8 {
+        !self.0
+    }
+
+    /// The number of the following block.
+    pub const fn next(self) -> Self {
+        Self(self.0.wrapping_add(1))
+    }
+
+    /// Whether `comp` is the one's complement of the block number `byte`.
+    pub const fn validate(byte: u8, comp: u8) -> bool {
+        Self(byte).complement() == comp
+    }
 }
 
+/// `Result` alias used throughout the crate, defaulting to `ModemError`.
 pub type ModemResult<T, E = ModemError> = Result<T, E>;
 
 mod utils {
-    use super::Read;
-    use core2::io::{ErrorKind, Result};
+    use super::{Read, Write};
+    use core::hash::Hasher;
+    use core2::io::{Error, ErrorKind, Result};
 
     pub fn calc_checksum(data: &[u8]) -> u8 {
         data.iter().fold(0, |x, &y| x.wrapping_add(y))
     }
 
+    /// Calculates the XMODEM CRC-16 of `data`, as sent after each block in
+    /// `ChecksumKind::Crc16` mode.
     pub fn calc_crc(data: &[u8]) -> u16 {
         crc16::State::<crc16::XMODEM>::calculate(data)
     }
 
+    /// A running XMODEM CRC-16, for data that arrives in pieces.
+    ///
+    /// Feeding the pieces to `update` in order gives the same result as
+    /// `calc_crc` over the whole of them, without having to collect them
+    /// first.
+    #[derive(Default, Debug, Copy, Clone)]
+    pub struct Crc16State(crc16::State<crc16::XMODEM>);
+
+    impl Crc16State {
+        /// Starts a CRC over no data.
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// Adds `data` to the CRC.
+        pub fn update(&mut self, data: &[u8]) {
+            self.0.update(data);
+        }
+
+        /// Returns the CRC of everything passed to `update`.
+        pub fn finalize(self) -> u16 {
+            self.0.get()
+        }
+    }
+
+    /// Calculates the CRC-16/CCITT-FALSE of `data`, as sent after each block
+    /// in `ChecksumKind::Crc16Ccitt` mode. It has the same polynomial as
+    /// `calc_crc`, but starts from 0xFFFF rather than 0.
+    pub fn calc_crc_ccitt(data: &[u8]) -> u16 {
+        crc16::State::<crc16::CCITT_FALSE>::calculate(data)
+    }
+
+    /// Feeds `data` to a running CRC-32 (the common IEEE 802.3 CRC) in
+    /// `crc`. Start from `!0` and invert the result, as `calc_crc32` does.
+    pub fn update_crc32(mut crc: u32, data: &[u8]) -> u32 {
+        for &b in data {
+            crc ^= u32::from(b);
+            for _ in 0..8 {
+                crc = if crc & 1 == 1 {
+                    (crc >> 1) ^ 0xEDB8_8320
+                } else {
+                    crc >> 1
+                };
+            }
+        }
+        crc
+    }
+
+    /// Calculates the CRC-32 (IEEE) of `data`, e.g. for the trailer checked
+    /// by `recv_with_trailer_check`.
+    pub fn calc_crc32(data: &[u8]) -> u32 {
+        !update_crc32(!0, data)
+    }
+
+    /// Returns the name of the control byte `byte`, e.g. `"ACK"` for `0x06`,
+    /// or `"unknown"` if it isn't one. Used to make error messages readable.
+    pub const fn control_byte_name(byte: u8) -> &'static str {
+        match byte {
+            0x00 => "NUL",
+            0x01 => "SOH",
+            0x02 => "STX",
+            0x04 => "EOT",
+            0x06 => "ACK",
+            0x08 => "BS",
+            0x86 => "ACK2",
+            0x10 => "DLE",
+            0x15 => "NAK",
+            0x18 => "CAN",
+            0x98 => "CAN2",
+            0x43 => "CRC",
+            0xC3 => "CRC2",
+            0x83 => "CRC3",
+            0x61 => "ABT",
+            _ => "unknown",
+        }
+    }
+
+    /// Writes `data` and flushes it, so buffered transports don't hold on to
+    /// a frame while both sides wait for each other.
+    pub fn put_bytes<W: Write>(writer: &mut W, data: &[u8]) -> Result<()> {
+        writer.write_all(data)?;
+        writer.flush()
+    }
+
+    /// The number of consecutive interrupted reads `get_byte` retries before
+    /// giving up.
+    pub const MAX_INTERRUPTED_READS: u32 = 8;
+
+    /// Reads a single byte, retrying reads interrupted by a signal up to
+    /// `MAX_INTERRUPTED_READS` times.
     pub fn get_byte<R: Read>(reader: &mut R) -> Result<u8> {
         let mut buff = [0];
-        reader.read_exact(&mut buff)?;
+        get_bytes(reader, &mut buff)?;
         Ok(buff[0])
     }
 
+    /// Reads at least one byte into `buf`, and as many more as one read of
+    /// the reader returns, i.e. those that had already arrived, returning the
+    /// number read. Interrupted reads are retried as in `get_byte`.
+    pub fn get_bytes<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<usize> {
+        let mut interrupts = 0;
+        loop {
+            match reader.read(buf) {
+                Ok(0) => {
+                    return Err(Error::new(
+                        ErrorKind::UnexpectedEof,
+                        "failed to read a byte",
+                    ))
+                }
+                Ok(n) => return Ok(n),
+                Err(err)
+                    if err.kind() == ErrorKind::Interrupted
+                        && interrupts < MAX_INTERRUPTED_READS =>
+                {
+                    interrupts += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Reads into `buf` until it is full or the reader reaches the end of
+    /// its data, returning the number of bytes read.
+    ///
+    /// Unlike a single `read`, a short read doesn't end the block early, so
+    /// only the final block of the data is ever padded.
+    pub fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<usize> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            match reader.read(&mut buf[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(err) if err.kind() == ErrorKind::Interrupted => (),
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(filled)
+    }
+
+    /// Reads and discards bytes until the line is quiet, i.e. a read times
+    /// out or the reader runs out of data.
+    ///
+    /// This clears out leftovers from a previous, aborted transfer. It only
+    /// returns once the other party stops sending.
+    pub fn purge<R: Read>(reader: &mut R) -> Result<()> {
+        loop {
+            match get_byte_timeout(reader) {
+                Ok(Some(_)) => (),
+                Ok(None) => return Ok(()),
+                Err(err) if err.kind() == ErrorKind::UnexpectedEof => {
+                    return Ok(())
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// A reader that pulls its bytes from an iterator.
+    pub struct IterReader<I> {
+        pub iter: I,
+    }
+
+    impl<I: Iterator<Item = u8>> Read for IterReader<I> {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            let mut n = 0;
+            for (slot, byte) in buf.iter_mut().zip(&mut self.iter) {
+                *slot = byte;
+                n += 1;
+            }
+            Ok(n)
+        }
+    }
+
+    /// A writer that feeds everything written through it to a hasher.
+    pub struct HashingWriter<'a, W, H> {
+        pub out: &'a mut W,
+        pub hasher: &'a mut H,
+    }
+
+    impl<W: Write, H: Hasher> Write for HashingWriter<'_, W, H> {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            let n = self.out.write(buf)?;
+            self.hasher.write(&buf[..n]);
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            self.out.flush()
+        }
+    }
+
     /// Turns timeout errors into `Ok(None)`
     pub fn get_byte_timeout<R: Read>(reader: &mut R) -> Result<Option<u8>> {
         match get_byte(reader) {
@@ -76,13 +517,22 @@ mod utils {
     }
 }
 
+/// Functionality shared by every -MODEM implementation.
 pub trait ModemTrait {
     /// Return a new instance of the `Xmodem` struct.
     fn new() -> Self
     where
         Self: Sized;
+
+    /// Returns whether an interrupted transfer can be resumed, rather than
+    /// started over, so generic code can offer it without knowing the
+    /// protocol.
+    fn supports_resume(&self) -> bool {
+        false
+    }
 }
 
+/// XMODEM functionality.
 pub trait XModemTrait: ModemTrait {
     /// Starts the XMODEM transmission.
     ///
@@ -93,19 +543,117 @@ pub trait XModemTrait: ModemTrait {
     /// This method has no way of setting the timeout of `dev`, so it's up to the caller
     /// to set the timeout of the device before calling this method. Timeouts on receiving
     /// bytes will be counted against `max_errors`, but timeouts on transmitting bytes
-    /// will be considered a fatal error.
+    /// will be considered a fatal error. While waiting for the receiver to start
+    /// the transmission, errors are counted against `max_initial_errors` instead.
     fn send<D: Read + Write, R: Read>(
         &mut self,
         dev: &mut D,
         inp: &mut R,
     ) -> ModemResult<()>;
 
+    /// Starts the XMODEM transmission, using `buf` as scratch space for every
+    /// block instead of the internal stack buffer.
+    ///
+    /// `buf` must be at least `block_length + 5` bytes long (header, data and
+    /// the largest checksum). See `send` for the remaining parameters.
+    fn send_with_buffer<D: Read + Write, R: Read>(
+        &mut self,
+        dev: &mut D,
+        inp: &mut R,
+        buf: &mut [u8],
+    ) -> ModemResult<()>;
+
+    /// Resumes an XMODEM transmission, numbering the first block sent
+    /// `start_block`.
+    ///
+    /// `inp` must already be positioned at the start of that block. XMODEM has
+    /// no native way to resume a transfer, so this is a cooperative extension:
+    /// the receiver has to be told out-of-band which block to expect first.
+    /// See `send` for the remaining parameters.
+    fn send_from<D: Read + Write, R: Read>(
+        &mut self,
+        dev: &mut D,
+        inp: &mut R,
+        start_block: u32,
+    ) -> ModemResult<()>;
+
+    /// Starts the XMODEM transmission of an in-memory buffer.
+    ///
+    /// This is `send` without wrapping `data` in a reader; see `send` for
+    /// details.
+    fn send_slice<D: Read + Write>(
+        &mut self,
+        dev: &mut D,
+        data: &[u8],
+    ) -> ModemResult<()> {
+        let mut inp = data;
+        self.send(dev, &mut inp)
+    }
+
+    /// Starts the XMODEM transmission of the bytes produced by `iter`, e.g.
+    /// a generated pattern or data decompressed on the fly.
+    ///
+    /// Bytes are pulled from `iter` one block at a time, and the last block
+    /// is padded as usual; see `send` for details.
+    fn send_iter<D: Read + Write, I: Iterator<Item = u8>>(
+        &mut self,
+        dev: &mut D,
+        iter: I,
+    ) -> ModemResult<()> {
+        self.send(dev, &mut IterReader { iter })
+    }
+
+    /// Sends the whole of `inp` without waiting for a receiver, for one-way
+    /// links (e.g. infrared or broadcast radio) with no channel back.
+    ///
+    /// Every block is framed as usual, with `checksum` as nothing is
+    /// negotiated, and written straight away, followed by a single `EOT`.
+    /// `dev` is never read. This is unreliable by design: a corrupted or
+    /// lost block is never resent, so the listeners have to cope with gaps
+    /// themselves, e.g. by repeating the broadcast. `inter_block_delay` is
+    /// called after each block, to pace the blocks for slow listeners.
+    fn send_broadcast<D: Write, R: Read>(
+        &mut self,
+        dev: &mut D,
+        inp: &mut R,
+        checksum: ChecksumKind,
+    ) -> ModemResult<()>;
+
+    /// Sends a single block, outside of any transfer, and returns the
+    /// receiver's response to it (usually `ACK`, `NAK` or `CAN`).
+    ///
+    /// This is meant for testing receivers: nothing is negotiated, and the
+    /// block is framed exactly as given, with `checksum` rather than the
+    /// receiver's choice, so that e.g. a mismatched checksum can be sent on
+    /// purpose. A payload of up to 128 bytes is sent as a 128-byte block, and
+    /// one of up to 1024 as a 1024-byte block, padded per `pad_policy`.
+    fn send_raw_block<D: Read + Write>(
+        &mut self,
+        dev: &mut D,
+        block_num: u8,
+        payload: &[u8],
+        checksum: ChecksumKind,
+    ) -> ModemResult<u8>;
+
     /// Receive an XMODEM transmission.
     ///
     /// `dev` should be the serial communication channel (e.g. the serial device).
     /// The received data will be written to `out`.
     /// `checksum` indicates which checksum mode should be used; `ChecksumKind::Standard` is
     /// a reasonable default.
+    /// An `XModem` with `receive_checksum` set (e.g. from `XModem::crc`) uses
+    /// that instead.
+    ///
+    /// Every block is read into the same fixed-size buffer and written
+    /// straight to `out`, so memory use doesn't grow with the size of the
+    /// transfer, and no allocator is needed.
+    ///
+    /// Both 128-byte (`SOH`) and 1024-byte (`STX`) blocks are accepted,
+    /// whatever `checksum` is. XMODEM-1k is normally paired with a CRC, but
+    /// some senders use 1024-byte blocks with the additive checksum; those
+    /// blocks are still followed by a single checksum byte, and are accepted.
+    /// Over a noisy line the additive checksum misses more errors in a block
+    /// that large, so prefer a CRC when the sender supports it.
     ///
     /// # Timeouts
     /// This method has no way of setting the timeout of `dev`, so it's up to the caller
@@ -119,6 +667,225 @@ pub trait XModemTrait: ModemTrait {
         checksum: ChecksumKind,
     ) -> ModemResult<()>;
 
+    /// Receive an XMODEM transmission, passing each block to `on_block`
+    /// instead of writing it out.
+    ///
+    /// The slice borrows a fixed buffer inside the receiver, so nothing is
+    /// copied or allocated per block; the final block includes its padding.
+    /// Each block is handed over before it is acknowledged, and if `on_block`
+    /// returns an error the transfer is canceled and the error returned.
+    /// See `receive` for the other parameters.
+    fn recv_blocks<D, F>(
+        &mut self,
+        dev: &mut D,
+        checksum: ChecksumKind,
+        on_block: F,
+    ) -> ModemResult<()>
+    where
+        D: Read + Write,
+        F: FnMut(&[u8]) -> ModemResult<()>;
+
+    /// Resumes receiving an XMODEM transmission, writing the data from block
+    /// `first_expected_block` onwards to `out`.
+    ///
+    /// `out` should already hold the blocks before it, e.g. from a transfer
+    /// that was interrupted. If the sender starts over from the first block
+    /// (see `XModem::first_block`), the blocks before `first_expected_block` are acknowledged and discarded;
+    /// if it resumes with `send_from`, its first block is numbered
+    /// `first_expected_block` and is accepted as is. As with `send_from`, the
+    /// two sides have to agree on the block out-of-band. See `receive` for
+    /// the other parameters.
+    fn recv_from<D: Read + Write, W: Write>(
+        &mut self,
+        dev: &mut D,
+        out: &mut W,
+        checksum: ChecksumKind,
+        first_expected_block: u8,
+    ) -> ModemResult<()>;
+
+    /// Receive an XMODEM transmission, feeding the received data to `hasher`
+    /// as it is written to `out`.
+    ///
+    /// This lets the caller check the integrity of the whole transfer without
+    /// a second pass over the data. The padding at the end of the final
+    /// block is trimmed as by `recv_trim_pad`, before either `out` or the
+    /// hasher sees it, so the hash can be compared with one of the original
+    /// data. See `receive` for the other parameters.
+    fn receive_hashed<D: Read + Write, W: Write, H: Hasher>(
+        &mut self,
+        dev: &mut D,
+        out: &mut W,
+        checksum: ChecksumKind,
+        pad: u8,
+        hasher: &mut H,
+    ) -> ModemResult<()> {
+        let mut out = HashingWriter { out, hasher };
+        self.recv_trim_pad(dev, &mut out, checksum, pad)
+    }
+
+    /// Receive an XMODEM transmission whose data ends with a CRC-32 of the
+    /// rest, and check it.
+    ///
+    /// This is an extension for protocols built on XMODEM: the sender appends
+    /// the big-endian CRC-32 (IEEE) of the data, and this writes the data
+    /// without the trailer to `out`. As XMODEM pads the final block, the
+    /// trailer may be followed by `pad` bytes (see `recv_trim_pad`), and the
+    /// trailer itself may end in `pad`; each possible end is tried. If none
+    /// matches, `ModemError::ChecksumMismatch` is returned. The transfer
+    /// itself has completed by then, so the sender isn't told.
+    ///
+    /// Everything but the final block and the 4 bytes before it is written as
+    /// it arrives, so on a mismatch `out` holds unchecked data. See `receive`
+    /// for the other parameters.
+    fn recv_with_trailer_check<D: Read + Write, W: Write>(
+        &mut self,
+        dev: &mut D,
+        out: &mut W,
+        checksum: ChecksumKind,
+        pad: u8,
+    ) -> ModemResult<()> {
+        // The trailer may start in the block before the final one, so the
+        // final block and the 4 bytes before it are held back.
+        let mut held = [0u8; BlockLengthKind::OneK as usize + 4];
+        let mut held_len = 0usize;
+        let mut crc = !0;
+        self.recv_blocks(dev, checksum, |block| {
+            let flushed = held_len.saturating_sub(4);
+            out.write_all(&held[..flushed])?;
+            crc = update_crc32(crc, &held[..flushed]);
+            held.copy_within(flushed..held_len, 0);
+            held_len -= flushed;
+            held[held_len..held_len + block.len()].copy_from_slice(block);
+            held_len += block.len();
+            Ok(())
+        })?;
+
+        let held = &held[..held_len];
+        let mut end = held.len();
+        while end >= 4 {
+            let (data, trailer) = held[..end].split_at(end - 4);
+            let expected = u32::from_be_bytes([
+                trailer[0], trailer[1], trailer[2], trailer[3],
+            ]);
+            if !update_crc32(crc, data) == expected {
+                return Ok(out.write_all(data)?);
+            }
+            if held[end - 1] != pad {
+                break;
+            }
+            end -= 1;
+        }
+        Err(ModemError::ChecksumMismatch)
+    }
+
+    /// Receive an XMODEM transmission, trimming the padding from the end of
+    /// the final block.
+    ///
+    /// XMODEM doesn't transmit the length of the data, so this guesses it:
+    /// a run of `pad` bytes (usually `0x1a`, see `PadPolicy`) at the
+    /// end of the final block is taken to be padding and not written to
+    /// `out`. Data that genuinely ends in `pad` is truncated too, so this is
+    /// only suitable for e.g. text, where that byte doesn't otherwise occur.
+    /// See `receive` for the other parameters.
+    fn recv_trim_pad<D: Read + Write, W: Write>(
+        &mut self,
+        dev: &mut D,
+        out: &mut W,
+        checksum: ChecksumKind,
+        pad: u8,
+    ) -> ModemResult<()> {
+        // Each block is held back until the next one arrives, as only the
+        // final block is trimmed.
+        let mut held = [0u8; BlockLengthKind::OneK as usize];
+        let mut held_len = 0;
+        self.recv_blocks(dev, checksum, |block| {
+            out.write_all(&held[..held_len])?;
+            held[..block.len()].copy_from_slice(block);
+            held_len = block.len();
+            Ok(())
+        })?;
+
+        let held = &held[..held_len];
+        let end = held.iter().rposition(|&b| b != pad).map_or(0, |i| i + 1);
+        Ok(out.write_all(&held[..end])?)
+    }
+
+    /// Receive a text file, removing the `^Z` (`0x1a`) padding that ends it.
+    ///
+    /// Only the run of `^Z`s at the very end of the final block is removed,
+    /// up to the last other byte; the blocks before it are written untouched,
+    /// so a `^Z` there is kept even if the final block is all padding. This
+    /// is for text transfers only: binary data that ends in `0x1a` would be
+    /// cut short.
+    ///
+    /// Returns the number of bytes written to `out`, and whether any padding
+    /// was removed. See `receive` for the other parameters.
+    fn recv_text<D: Read + Write, W: Write>(
+        &mut self,
+        dev: &mut D,
+        out: &mut W,
+        checksum: ChecksumKind,
+    ) -> ModemResult<(u64, bool)> {
+        // As in `recv_trim_pad`, each block is held back until the next one
+        // arrives.
+        let mut held = [0u8; BlockLengthKind::OneK as usize];
+        let mut held_len = 0;
+        let mut written = 0u64;
+        self.recv_blocks(dev, checksum, |block| {
+            out.write_all(&held[..held_len])?;
+            written += held_len as u64;
+            held[..block.len()].copy_from_slice(block);
+            held_len = block.len();
+            Ok(())
+        })?;
+
+        let held = &held[..held_len];
+        let end = held.iter().rposition(|&b| b != 0x1a).map_or(0, |i| i + 1);
+        out.write_all(&held[..end])?;
+        Ok((written + end as u64, end < held.len()))
+    }
+
+    /// Receive an XMODEM transmission into `buf`, returning the number of
+    /// bytes written, e.g. to stage a firmware image without a `Write`
+    /// implementation.
+    ///
+    /// `buf` only needs room for the data: the part of the final block that
+    /// doesn't fit is dropped, as long as it is padding (`0x1a` or `0x00`,
+    /// see `PadPolicy`). The count includes any padding that did fit. If the
+    /// sender sends more data than that, the transfer is canceled with
+    /// `ModemError::SizeLimitExceeded`. See `receive` for the other
+    /// parameters.
+    fn recv_into<D: Read + Write>(
+        &mut self,
+        dev: &mut D,
+        buf: &mut [u8],
+        checksum: ChecksumKind,
+    ) -> ModemResult<usize> {
+        let limit = buf.len() as u64;
+        let mut len = 0;
+        let mut full = false;
+        self.recv_blocks(dev, checksum, |block| {
+            let (fits, rest) = block.split_at(block.len().min(buf.len() - len));
+            if full || rest.iter().any(|&b| b != 0x1a && b != 0x00) {
+                return Err(ModemError::SizeLimitExceeded { limit });
+            }
+            buf[len..len + fits.len()].copy_from_slice(fits);
+            len += fits.len();
+            full = len == buf.len();
+            Ok(())
+        })?;
+        Ok(len)
+    }
+
+    /// Cancels a transmission in progress.
+    ///
+    /// Writes eight `CAN` bytes, followed by eight backspaces to clear them
+    /// from the other party's terminal, then flushes `dev`. This can be called
+    /// from outside the transfer, e.g. on another handle to the same device.
+    fn abort<D: Write>(dev: &mut D) -> ModemResult<()>
+    where
+        Self: Sized;
+
     /// Internal function for initializing a transmission.
     /// FIXME: Document.
     fn init_send<D: Read + Write>(&mut self, dev: &mut D) -> ModemResult<()>;
@@ -128,23 +895,112 @@ pub trait XModemTrait: ModemTrait {
     fn finish_send<D: Read + Write>(&mut self, dev: &mut D) -> ModemResult<()>;
 
     /// Internal function for sending a stream.
-    /// FIXME: Document.
+    /// The last block is padded according to the sender's `PadPolicy`.
     fn send_stream<D: Read + Write, R: Read>(
         &mut self,
         dev: &mut D,
         inp: &mut R,
     ) -> ModemResult<()>;
+
+    /// Internal function for sending a stream, reusing `buf` for every block.
+    /// `buf` must be at least `block_length + 5` bytes long.
+    fn send_stream_with_buffer<D: Read + Write, R: Read>(
+        &mut self,
+        dev: &mut D,
+        inp: &mut R,
+        buf: &mut [u8],
+    ) -> ModemResult<()>;
+}
+
+/// Metadata decoded from a YMODEM header block.
+#[cfg(feature = "alloc")]
+#[allow(dead_code)] // TODO: Temporarily allow this lint, whilst I work out YMODEM support.
+#[derive(Default, Clone, Debug)]
+pub struct YModemFileInfo {
+    /// The file name sent by the other party. This comes from an untrusted
+    /// peer, so it should be validated before being used as a path.
+    ///
+    /// YMODEM doesn't specify an encoding; names are usually ASCII, and
+    /// sometimes Latin-1 or UTF-8. The name is decoded as UTF-8, and bytes
+    /// that aren't valid UTF-8 (e.g. Latin-1 accents, or a multi-byte
+    /// character cut short by a sender that truncated the name) are replaced
+    /// with U+FFFD, so decoding never fails.
+    pub file_name: String,
+    /// The file size sent by the other party.
+    pub file_size: u64,
+}
+
+#[cfg(feature = "alloc")]
+#[allow(dead_code)] // TODO: Temporarily allow this lint, whilst I work out YMODEM support.
+impl YModemFileInfo {
+    /// Decodes the data of a YMODEM header block: the file name, a NUL, then
+    /// the file size in decimal, optionally followed by a space and further
+    /// fields, which are ignored.
+    ///
+    /// A size field that is empty or isn't a number is an
+    /// `ModemError::InvalidHeader`. Some senders append junk to the size, so
+    /// with `ignore_non_digits` set, any non-digits in it are skipped instead;
+    /// it is still invalid if no digits are left.
+    pub fn from_header(
+        data: &[u8],
+        ignore_non_digits: bool,
+    ) -> ModemResult<Self> {
+        let mut fields = data.splitn(2, |&b| b == 0);
+        let name = fields.next().unwrap_or_default();
+        let size = fields.next().ok_or(ModemError::InvalidHeader)?;
+        let size = size.split(|&b| b == 0).next().unwrap_or_default();
+        let size = size.split(|&b| b == b' ').next().unwrap_or_default();
+
+        let mut file_size = 0u64;
+        let mut digits = 0;
+        for &b in size {
+            if b.is_ascii_digit() {
+                file_size = file_size
+                    .checked_mul(10)
+                    .and_then(|n| n.checked_add(u64::from(b - b'0')))
+                    .ok_or(ModemError::InvalidHeader)?;
+                digits += 1;
+            } else if !ignore_non_digits {
+                return Err(ModemError::InvalidHeader);
+            }
+        }
+        if digits == 0 {
+            return Err(ModemError::InvalidHeader);
+        }
+
+        Ok(Self {
+            file_name: String::from_utf8_lossy(name).into_owned(),
+            file_size,
+        })
+    }
 }
 
+#[cfg(feature = "alloc")]
 #[allow(dead_code)] // TODO: Temporarily allow this lint, whilst I work out YMODEM support.
 pub trait YModemTrait: ModemTrait {
+    /// Receive a single YMODEM file into `out`, storing its name and size.
+    ///
+    /// An empty file is valid: its header announces a size of 0 and is
+    /// followed directly by `EOT`, so nothing is written to `out` and
+    /// `file_size` is set to 0.
+    ///
+    /// The end of the transfer is found from the line, not from the size in
+    /// the header: data blocks are accepted until the sender's `EOT`, which
+    /// is NAKed once and ACKed when repeated, and the receiver then keeps
+    /// going until the empty header that ends the batch. The size is only
+    /// used to trim the padding, so a wrong size can't cut the closing
+    /// handshake short and leave the sender waiting.
     fn recv<D: Read + Write, W: Write>(
         &mut self,
         dev: &mut D,
         out: &mut W,
         file_name: &mut String,
-        file_size: &mut u32,
+        file_size: &mut u64,
     ) -> ModemResult<()>;
+    /// Sends `file_size` bytes of `inp` as a single file named `file_name`.
+    ///
+    /// A `file_size` of 0 sends the header block, then `EOT` without any data
+    /// blocks, then the empty header that ends the batch.
     fn send<D: Read + Write, R: Read>(
         &mut self,
         dev: &mut D,
@@ -152,6 +1008,17 @@ pub trait YModemTrait: ModemTrait {
         file_name: String,
         file_size: u64,
     ) -> ModemResult<()>;
+    /// Sends an in-memory buffer as a single file named `file_name`, using
+    /// the buffer's length as the file size.
+    fn send_slice<D: Read + Write>(
+        &mut self,
+        dev: &mut D,
+        data: &[u8],
+        file_name: String,
+    ) -> ModemResult<()> {
+        let mut inp = data;
+        self.send(dev, &mut inp, file_name, data.len() as u64)
+    }
     fn send_stream<D: Read + Write, R: Read>(
         &mut self,
         dev: &mut D,
@@ -159,6 +1026,13 @@ pub trait YModemTrait: ModemTrait {
         packets_to_send: u32,
         last_packet_size: u64,
     ) -> ModemResult<()>;
+    /// Sends the header block for a file: its name, a NUL byte, then its size.
+    ///
+    /// The size must be written in decimal, as the YMODEM spec requires and
+    /// as `recv` parses it. The NUL after the name has to be written
+    /// explicitly rather than relying on the block being zeroed beforehand:
+    /// `recv` splits the name from the size at the first NUL, so without it
+    /// the size would be read as part of the name.
     fn send_start_frame<D: Read + Write>(
         &mut self,
         dev: &mut D,
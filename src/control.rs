@@ -0,0 +1,39 @@
+//! Helpers for writing single XMODEM control bytes, for test harnesses and
+//! state machines that drive the protocol themselves.
+//! Guarded by the `xmodem` feature flag.
+//! Disabled by default.
+
+use crate::common::put_bytes;
+use crate::variants::xmodem::{Consts, ModemResult};
+use core2::io::Write;
+
+/// Writes `byte` and flushes it out.
+fn send<D: Write>(dev: &mut D, byte: Consts) -> ModemResult<()> {
+    put_bytes(dev, &[byte.into()])?;
+    Ok(())
+}
+
+/// Writes an `EOT`, ending a transmission.
+pub fn send_eot<D: Write>(dev: &mut D) -> ModemResult<()> {
+    send(dev, Consts::EOT)
+}
+
+/// Writes an `ACK`, accepting a block.
+pub fn send_ack<D: Write>(dev: &mut D) -> ModemResult<()> {
+    send(dev, Consts::ACK)
+}
+
+/// Writes a `NAK`, rejecting a block (or, from a receiver that hasn't
+/// started yet, asking for the additive checksum).
+pub fn send_nak<D: Write>(dev: &mut D) -> ModemResult<()> {
+    send(dev, Consts::NAK)
+}
+
+/// Writes the sequence that cancels a transfer: 8 `CAN`s, then 8 `BS`s to
+/// erase them from a terminal that echoed them.
+pub fn send_can_sequence<D: Write>(dev: &mut D) -> ModemResult<()> {
+    let mut sequence = [Consts::CAN.into(); 16];
+    sequence[8..].fill(Consts::BS.into());
+    put_bytes(dev, &sequence)?;
+    Ok(())
+}
@@ -0,0 +1,83 @@
+//! An adapter for using `embedded-hal-nb` serial ports as a transport.
+//! Guarded by the `embedded-hal-nb` feature flag.
+//! Disabled by default.
+
+use core2::io::{Error, ErrorKind, Read, Result, Write};
+use embedded_hal_nb::nb;
+use embedded_hal_nb::serial;
+
+/// The number of times `EmbeddedHalSerial` polls the port by default before
+/// reporting a timeout.
+pub const DEFAULT_SPIN_BUDGET: u32 = 100_000;
+
+/// Wraps a byte-at-a-time `embedded-hal-nb` serial port as the `Read` and
+/// `Write` channel used by the transfers.
+///
+/// The port is polled until it is ready. If it is still not ready after
+/// `spin_budget` polls, the read or write fails with `ErrorKind::TimedOut`,
+/// which the transfers handle like a read timeout on a serial device. The
+/// budget should be tuned to the port's speed, so that it takes a few
+/// seconds to run out.
+#[derive(Debug)]
+pub struct EmbeddedHalSerial<T> {
+    inner: T,
+    /// The number of polls before a read or write times out.
+    pub spin_budget: u32,
+}
+
+impl<T> EmbeddedHalSerial<T> {
+    /// Wraps `inner`, with `DEFAULT_SPIN_BUDGET`.
+    pub const fn new(inner: T) -> Self {
+        Self {
+            inner,
+            spin_budget: DEFAULT_SPIN_BUDGET,
+        }
+    }
+
+    /// Returns the wrapped port.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    /// Polls `f` until it stops blocking, or the spin budget runs out.
+    fn spin<R, E>(
+        &mut self,
+        mut f: impl FnMut(&mut T) -> nb::Result<R, E>,
+    ) -> Result<R> {
+        for _ in 0..self.spin_budget {
+            match f(&mut self.inner) {
+                Ok(r) => return Ok(r),
+                Err(nb::Error::WouldBlock) => (),
+                Err(nb::Error::Other(_)) => {
+                    // `core2`'s `Error` has no `other` without `std`.
+                    #[allow(clippy::io_other_error)]
+                    return Err(Error::new(ErrorKind::Other, "serial error"));
+                }
+            }
+        }
+        Err(Error::new(ErrorKind::TimedOut, "serial port not ready"))
+    }
+}
+
+impl<T: serial::Read> Read for EmbeddedHalSerial<T> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let Some(first) = buf.first_mut() else {
+            return Ok(0);
+        };
+        *first = self.spin(serial::Read::read)?;
+        Ok(1)
+    }
+}
+
+impl<T: serial::Write> Write for EmbeddedHalSerial<T> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        for &byte in buf {
+            self.spin(|port| port.write(byte))?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.spin(serial::Write::flush)
+    }
+}
@@ -16,7 +16,14 @@
     variant_size_differences
 )]
 
+#[cfg(feature = "alloc")]
 extern crate alloc;
 
 mod common;
+#[cfg(feature = "xmodem")]
+pub mod control;
+#[cfg(feature = "embedded-hal-nb")]
+pub mod embedded_hal;
+#[cfg(feature = "test-util")]
+pub mod test_util;
 pub mod variants;
@@ -0,0 +1,232 @@
+//! Utilities for testing -MODEM transfers without a real serial device.
+//! Guarded by the `test-util` feature flag, which enables `std`.
+//! Disabled by default.
+#![cfg_attr(
+    feature = "xmodem",
+    doc = r#"
+A complete XMODEM transfer over a `Loopback`, with the sender on another
+thread:
+
+```
+use std::thread;
+use txmodems::test_util::loopback_pair;
+use txmodems::variants::xmodem::{
+    ChecksumKind, ModemTrait, XModem, XModemTrait,
+};
+
+let (mut sender_end, mut receiver_end) = loopback_pair();
+let sender = thread::spawn(move || {
+    XModem::new().send(&mut sender_end, &mut &b"Hello, XMODEM!"[..])
+});
+
+let mut out = Vec::new();
+XModem::new().receive(&mut receiver_end, &mut out, ChecksumKind::Crc16)?;
+sender.join().unwrap()?;
+// The final block is padded to 128 bytes with ^Z.
+assert_eq!(&out[..14], b"Hello, XMODEM!");
+assert_eq!(out.len(), 128);
+# Ok::<(), txmodems::variants::xmodem::ModemError>(())
+```
+"#
+)]
+
+extern crate std;
+
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use core::time::Duration;
+use core2::io::{Error, ErrorKind, Read, Result, Write};
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+
+/// How long a `Loopback` waits for data before reporting a timeout.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Default)]
+struct Pipe {
+    buf: VecDeque<u8>,
+    timeouts: u32,
+    bit_error: u8,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    pipe: Mutex<Pipe>,
+    ready: Condvar,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, Pipe> {
+        self.pipe.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// One end of an in-memory, bidirectional channel.
+///
+/// Bytes written to one end can be read from the other, so a sender and a
+/// receiver running on separate threads can talk to each other as if they
+/// were connected by a serial line. Reads block for up to `timeout`, then fail
+/// with `ErrorKind::TimedOut`, like a serial device with a read timeout.
+///
+/// A clone is another handle to the same end, as `try_clone` gives for a
+/// serial port, e.g. to call `bytes_to_read` while a transfer borrows it.
+#[derive(Debug, Clone)]
+pub struct Loopback {
+    rx: Arc<Shared>,
+    tx: Arc<Shared>,
+    /// How long a read waits for data before timing out.
+    pub timeout: Duration,
+}
+
+/// Returns two connected `Loopback` ends.
+pub fn loopback_pair() -> (Loopback, Loopback) {
+    let a = Arc::new(Shared::default());
+    let b = Arc::new(Shared::default());
+
+    (
+        Loopback {
+            rx: a.clone(),
+            tx: b.clone(),
+            timeout: DEFAULT_TIMEOUT,
+        },
+        Loopback {
+            rx: b,
+            tx: a,
+            timeout: DEFAULT_TIMEOUT,
+        },
+    )
+}
+
+impl Loopback {
+    /// Makes the next `count` reads on this end time out, regardless of
+    /// whether any data is waiting.
+    pub fn inject_timeouts(&self, count: u32) {
+        self.rx.lock().timeouts += count;
+    }
+
+    /// Returns the number of bytes waiting to be read on this end.
+    pub fn bytes_to_read(&self) -> usize {
+        self.rx.lock().buf.len()
+    }
+
+    /// Flips the bits set in `mask` in the next byte read on this end.
+    pub fn inject_bit_error(&self, mask: u8) {
+        self.rx.lock().bit_error = mask;
+    }
+}
+
+impl Read for Loopback {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let mut pipe = self.rx.lock();
+        if pipe.timeouts > 0 {
+            pipe.timeouts -= 1;
+            return Err(Error::new(ErrorKind::TimedOut, "injected timeout"));
+        }
+
+        let (mut pipe, _) = self
+            .rx
+            .ready
+            .wait_timeout_while(pipe, self.timeout, |p| p.buf.is_empty())
+            .unwrap_or_else(PoisonError::into_inner);
+
+        let n = buf.len().min(pipe.buf.len());
+        if n == 0 {
+            return Err(Error::new(ErrorKind::TimedOut, "read timed out"));
+        }
+
+        for (dst, src) in buf.iter_mut().zip(pipe.buf.drain(..n)) {
+            *dst = src;
+        }
+        buf[0] ^= pipe.bit_error;
+        pipe.bit_error = 0;
+
+        Ok(n)
+    }
+}
+
+impl Write for Loopback {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.tx.lock().buf.extend(buf);
+        self.tx.ready.notify_all();
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// A wrapper that injects faults into the bytes written to `T`.
+///
+/// Each fault is configured with the index of the written byte it applies
+/// to, counting from zero across the whole transfer. Reads are passed through
+/// untouched.
+#[derive(Debug)]
+pub struct FaultyChannel<T> {
+    inner: T,
+    written: usize,
+    /// Flip every bit of the byte at this index.
+    pub corrupt_at: Option<usize>,
+    /// Drop the byte at this index, so it is never delivered.
+    pub drop_at: Option<usize>,
+    /// Deliver the byte at this index twice.
+    pub duplicate_at: Option<usize>,
+    /// The number of faults injected so far.
+    pub faults: u32,
+}
+
+impl<T> FaultyChannel<T> {
+    /// Wraps `inner`, initially injecting no faults.
+    pub const fn new(inner: T) -> Self {
+        Self {
+            inner,
+            written: 0,
+            corrupt_at: None,
+            drop_at: None,
+            duplicate_at: None,
+            faults: 0,
+        }
+    }
+
+    /// Returns the wrapped channel.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: Read> Read for FaultyChannel<T> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl<T: Write> Write for FaultyChannel<T> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        for &b in buf {
+            let i = self.written;
+            self.written += 1;
+
+            if self.drop_at == Some(i) {
+                self.faults += 1;
+            } else if self.corrupt_at == Some(i) {
+                self.faults += 1;
+                self.inner.write_all(&[!b])?;
+            } else if self.duplicate_at == Some(i) {
+                self.faults += 1;
+                self.inner.write_all(&[b, b])?;
+            } else {
+                self.inner.write_all(&[b])?;
+            }
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
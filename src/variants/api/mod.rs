@@ -1,5 +1,26 @@
+#[cfg(feature = "xmodem")]
+pub(crate) mod modem;
+
 #[cfg(feature = "xmodem")]
 pub(crate) mod xmodem;
 
+#[cfg(feature = "xmodem")]
+pub(crate) mod xmodem_buffered;
+
+#[cfg(feature = "xmodem")]
+pub(crate) mod xmodem_decoder;
+
+#[cfg(feature = "xmodem")]
+pub(crate) mod xmodem_roles;
+
+#[cfg(feature = "xmodem")]
+pub(crate) mod xmodem_trace;
+
+#[cfg(feature = "xmodem")]
+pub(crate) mod xmodem_windowed;
+
+#[cfg(all(feature = "xmodem", feature = "std"))]
+pub(crate) mod xmodem_file;
+
 #[cfg(feature = "ymodem")]
 pub(crate) mod ymodem;
@@ -0,0 +1,90 @@
+use crate::common::{ModemError, ModemResult, ModemTrait, XModemTrait};
+use core2::io::{Read, Write};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::variants::api::xmodem::XModem;
+use crate::variants::xmodem::common::ChecksumKind;
+
+/// The protocol a `Modem` transfers with.
+///
+/// With the `serde` feature, this is (de)serialized as `"xmodem"` or
+/// `"ymodem"`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Protocol {
+    /// XMODEM, with the settings in `Modem::xmodem`.
+    Xmodem,
+    /// YMODEM. This is not implemented yet, so every transfer fails with
+    /// `ModemError::UnsupportedProtocol`.
+    Ymodem,
+}
+
+/// Transfers with a protocol chosen at runtime, e.g. from a command-line
+/// flag, so that a program has a single call site for every protocol.
+///
+/// The methods take every parameter any of the protocols needs. Those a
+/// protocol has no use for are optional, and ignored by it.
+#[derive(Debug, Copy, Clone)]
+pub struct Modem {
+    /// The protocol to transfer with.
+    pub protocol: Protocol,
+    /// The settings used for XMODEM transfers.
+    pub xmodem: XModem,
+}
+
+impl Modem {
+    /// Creates a `Modem` for `protocol`, with its default settings.
+    pub fn new(protocol: Protocol) -> Self {
+        Self {
+            protocol,
+            xmodem: XModem::new(),
+        }
+    }
+
+    /// Sends the whole of `inp`.
+    ///
+    /// YMODEM sends the file's name and size ahead of its data. XMODEM has
+    /// no such header, so it ignores `file_name` and `file_size`.
+    pub fn send<D, R>(
+        &mut self,
+        dev: &mut D,
+        inp: &mut R,
+        file_name: Option<&str>,
+        file_size: Option<u64>,
+    ) -> ModemResult<()>
+    where
+        D: Read + Write,
+        R: Read,
+    {
+        match self.protocol {
+            Protocol::Xmodem => self.xmodem.send(dev, inp),
+            Protocol::Ymodem => {
+                let _ = (file_name, file_size);
+                Err(ModemError::UnsupportedProtocol)
+            }
+        }
+    }
+
+    /// Receives a transmission into `out`.
+    ///
+    /// `checksum` is the mode XMODEM asks the sender for, as with
+    /// `XModemTrait::receive`; YMODEM always uses a CRC.
+    pub fn recv<D, W>(
+        &mut self,
+        dev: &mut D,
+        out: &mut W,
+        checksum: ChecksumKind,
+    ) -> ModemResult<()>
+    where
+        D: Read + Write,
+        W: Write,
+    {
+        match self.protocol {
+            Protocol::Xmodem => self.xmodem.receive(dev, out, checksum),
+            Protocol::Ymodem => Err(ModemError::UnsupportedProtocol),
+        }
+    }
+}
@@ -1,39 +1,264 @@
-use alloc::{boxed::Box, vec, vec::Vec};
 use core::convert::From;
 
 use crate::common::{
-    calc_checksum, calc_crc, get_byte, get_byte_timeout, ModemError,
-    ModemResult, ModemTrait, XModemTrait,
+    calc_checksum, calc_crc, calc_crc_ccitt, get_byte, get_byte_timeout,
+    get_bytes, purge, put_bytes, read_full, ModemError, ModemEvent,
+    ModemResult, ModemTrait, PacketNumber, PadPolicy, TransferStats,
+    XModemTrait,
 };
-use core2::io::{Read, Write};
+use crate::control::send_can_sequence;
+use core2::io::{Error, ErrorKind, Read, Write};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 use crate::variants::xmodem::{
     common::{BlockLengthKind, ChecksumKind},
     Consts,
 };
 
-// TODO: Send CAN byte after too many errors
-// TODO: Handle CAN bytes while sending
 // TODO: Implement Error for Error
 
 /// `Xmodem` acts as state for XMODEM transfers
-#[derive(Default, Debug, Copy, Clone)]
+///
+/// The public fields are the settings. The error counters and statistics of
+/// the current transfer live in an `XModemState` (see `state`), so copying a
+/// configured `XModem` is a cheap way to share its settings.
+///
+/// With the `serde` feature, the settings can be (de)serialized, e.g. from a
+/// config file. The `event_sink` and the state of the current transfer are
+/// skipped. Settings missing from the input keep their `Default` values, so a
+/// config file only needs to list the ones it changes.
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct XModem {
     /// The number of errors that can occur before the communication is
     /// considered a failure. Errors include unexpected bytes and timeouts waiting for bytes.
     pub max_errors: u32,
+    /// The number of times a single block can be retried before its retries
+    /// start counting against `max_errors`.
+    ///
+    /// With the default of 0 every retry counts. Raising it lets a transfer
+    /// with several bad blocks complete, as long as each block eventually
+    /// gets through.
+    pub max_block_retries: u32,
+    /// The number of *initial errors* that can occur before the communication is
+    /// considered a failure. Errors include unexpected bytes and timeouts waiting for bytes.
+    ///
+    /// These are only counted while waiting for the receiver to start the
+    /// transmission, and do not count against `max_errors`.
+    pub max_initial_errors: u32,
 
-    /// The byte used to pad the last block. XMODEM can only send blocks of a certain size,
+    /// How the last block is padded. XMODEM can only send blocks of a certain size,
     /// so if the message is not a multiple of that size the last block needs to be padded.
-    pub pad_byte: u8,
+    /// See `PadPolicy` for how receivers handle each policy.
+    pub pad_policy: PadPolicy,
 
     /// The length of each block. There are only two options: 128-byte blocks (standard
-    ///  XMODEM) or 1024-byte blocks (XMODEM-1k).
+    ///  XMODEM) or 1024-byte blocks (XMODEM-1k). With 1024-byte blocks, a final
+    ///  block holding 128 bytes or less is sent as a 128-byte block.
     pub block_length: BlockLengthKind,
 
+    /// The number of the first block of a transfer, 1 by default as the
+    /// protocol specifies.
+    ///
+    /// Some embedded bootloaders number their blocks from 0 instead. Set this
+    /// to 0 on both ends to talk to one. Both ends have to agree: a receiver
+    /// expecting block 1 rejects a block 0 as out of sequence, and vice
+    /// versa, so a mismatch makes the transfer fail rather than lose data.
+    pub first_block: u8,
+
+    /// Whether `supports_resume` reports that an interrupted transfer can be
+    /// resumed with `send_from` and `recv_from`. True by default; turn it off
+    /// when the other end is known to always start over at the first block,
+    /// so that generic code doesn't offer to resume.
+    pub resumable: bool,
+
+    /// The length of every received block, whatever its header byte says,
+    /// or `None` to go by the header (`SOH` for 128 bytes, `STX` for 1024).
+    ///
+    /// Some broken senders start 1024-byte blocks with `SOH`; set this to
+    /// `BlockLengthKind::OneK` to receive from them. It turns off detecting
+    /// the length from the header, so blocks of the other length, such as
+    /// the short final block many XMODEM-1k senders use, are then misread.
+    pub force_block_len: Option<BlockLengthKind>,
+
+    /// The number of times the receiver sends its initial `NAK` or `C`, once
+    /// per timeout, before giving up on the sender starting the transmission.
+    pub poll_count: u32,
+
+    /// The byte the receiver sends to start the transmission, instead of
+    /// `NAK` or `C`, for devices that expect something else.
+    ///
+    /// The checksum mode is still the one passed to `receive` (or
+    /// `receive_checksum`), so when the byte asks for CRC mode, use a CRC
+    /// `ChecksumKind` as well.
+    pub init_byte_override: Option<u8>,
+
+    /// The checksum mode `receive` (and the other receiving methods) use,
+    /// instead of the one passed to them. The poll byte follows from it.
+    ///
+    /// This is set by the `crc` and `standard` presets. It has no effect on
+    /// sending, where the receiver chooses the mode.
+    pub receive_checksum: Option<ChecksumKind>,
+
+    /// The most data a receive accepts, in bytes, or `None` for no limit.
+    ///
+    /// XMODEM doesn't send the length of the data, so a runaway or hostile
+    /// sender could keep sending blocks until e.g. the disk or a flash
+    /// region is full. Once a block would take the total over this limit,
+    /// the transfer is canceled with `ModemError::SizeLimitExceeded` before
+    /// the block is written. The limit counts whole blocks, including the
+    /// padding of the last one, so allow for up to a block more than the
+    /// expected size.
+    pub max_recv_bytes: Option<u64>,
+
+    /// The longest run of bytes a receive tolerates that are neither a block
+    /// header nor a control byte, or `None` for no limit.
+    ///
+    /// Control bytes that arrive out of place (e.g. an `ACK` from a confused
+    /// sender) are counted against `max_errors` as usual, but end the run.
+    ///
+    /// Once the run reaches this, the transfer is canceled with
+    /// `ModemError::LineError`, rather than going on to exhaust `max_errors`
+    /// on what is most likely a baud rate mismatch. Before the first block,
+    /// bytes from a block the sender started early count too, so when that
+    /// can happen allow for a whole block (up to 1029 bytes).
+    pub max_unrecognized: Option<u32>,
+
+    /// Use CRC-16/CCITT-FALSE instead of the XMODEM CRC when the receiver asks
+    /// for CRC mode. See `ChecksumKind::Crc16Ccitt`.
+    pub use_crc_ccitt: bool,
+
+    /// Calculates the 16-bit CRC of a block's data in place of the built-in
+    /// ones, for devices using a nonstandard CRC (a different polynomial,
+    /// initial value or reflection).
+    ///
+    /// It is used whenever a CRC mode is in effect, both when sending and
+    /// when receiving, and overrides `use_crc_ccitt`. Both ends must of
+    /// course use the same CRC. The CRC is still sent most significant byte
+    /// first.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub crc_fn: Option<fn(&[u8]) -> u16>,
+
+    /// Discard anything left on the line (see `purge`) before starting a
+    /// transfer.
+    pub purge_on_start: bool,
+
+    /// The number of back-to-back `CAN` bytes that cancel the transfer. See
+    /// `CanDetector`.
+    pub can_threshold: u32,
+
+    /// The number of back-to-back `ABT` bytes (`a` or `A`) that cancel the
+    /// transfer, or 0 to ignore them. See `CanDetector`.
+    pub abt_threshold: u32,
+
+    /// Called with each protocol event as the transfer progresses, to observe
+    /// it without a logging backend. This is a plain function pointer so that
+    /// `XModem` stays `Copy`; for a closure that keeps state, pass it in
+    /// `TransferHooks` instead.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub event_sink: Option<fn(ModemEvent)>,
+
+    /// Returns whether a byte can be read from the channel without blocking,
+    /// e.g. by checking a UART's receive flag. See `TransferHooks` for a
+    /// closure, e.g. one holding a clone of a serial port.
+    ///
+    /// When sending, the waiting bytes are read between blocks to look for
+    /// `CAN` or `ABT` bytes, so that a cancel is noticed before the next block
+    /// goes out rather than when it is acknowledged. Any other byte waiting
+    /// there is a stale response, e.g. a duplicated `NAK` for a block that has
+    /// since been acknowledged; it is discarded and reported as
+    /// `ModemEvent::StaleResponse`, rather than being read as the response to
+    /// the next block.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub byte_ready: Option<fn() -> bool>,
+
+    /// Called after each block is acknowledged, before the next one is sent,
+    /// to give a slow receiver time to e.g. write the block to flash. The
+    /// function can sleep or busy-wait for as long as the receiver needs.
+    /// `send_broadcast` calls it after each block instead. `XModemWindowed`
+    /// doesn't wait for each block, so it ignores this. See `TransferHooks`
+    /// for a closure.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub inter_block_delay: Option<fn()>,
+
+    /// Called once per block while sending, and once per block or other
+    /// byte read while receiving, so that on a cooperative scheduler the
+    /// caller can e.g. feed a watchdog or let other tasks run. It may be
+    /// called many times while waiting on a slow line. See `TransferHooks`
+    /// for a closure.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub yield_hook: Option<fn()>,
+
+    /// The state of the current transfer.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) state: XModemState,
+}
+
+/// The number of blocks kept in the retry log. See
+/// `XModemState::recent_retries`.
+pub const RETRY_LOG_LEN: usize = 16;
+
+/// The state of an XMODEM transfer, kept apart from the settings in `XModem`.
+///
+/// A configured `XModem` can be copied and used for several transfers, e.g.
+/// one per thread; each copy gets its own state, which is cleared when a
+/// transfer starts.
+#[derive(Default, Debug, Copy, Clone)]
+pub struct XModemState {
     /// The checksum mode used by XMODEM. This is determined by the receiver.
-    checksum_mode: ChecksumKind,
-    errors: u32,
+    pub(crate) checksum_mode: ChecksumKind,
+    pub(crate) errors: u32,
+    pub(crate) initial_errors: u32,
+    /// The number of `errors` that were timeouts.
+    pub(crate) timeouts: u32,
+    pub(crate) stats: TransferStats,
+    /// The number of bytes received and accepted so far.
+    pub(crate) received: u64,
+    /// The blocks that needed retries, oldest first. See `recent_retries`.
+    pub(crate) retry_log: [(u8, u8); RETRY_LOG_LEN],
+    pub(crate) retry_log_len: usize,
+}
+
+impl XModemState {
+    /// Returns the checksum mode of the transfer.
+    pub fn checksum_mode(&self) -> ChecksumKind {
+        self.checksum_mode
+    }
+
+    /// Returns the number of errors counted against `max_errors`.
+    pub fn errors(&self) -> u32 {
+        self.errors
+    }
+
+    /// Returns the number of errors counted against `max_initial_errors`.
+    pub fn initial_errors(&self) -> u32 {
+        self.initial_errors
+    }
+
+    /// Returns the statistics of the transfer.
+    pub fn stats(&self) -> TransferStats {
+        self.stats
+    }
+
+    /// Returns the last `RETRY_LOG_LEN` blocks that needed retries, oldest
+    /// first, as pairs of the block's number on the wire and its number of
+    /// retries (saturating at 255).
+    ///
+    /// If the transfer failed because a block ran out of retries, that block
+    /// is the last one. This is meant for diagnosing a link in the field,
+    /// where there may be no logger.
+    pub fn recent_retries(&self) -> &[(u8, u8)] {
+        &self.retry_log[..self.retry_log_len]
+    }
+}
+
+// The same as `new`, so that a default instance is usable.
+impl Default for XModem {
+    fn default() -> Self {
+        <Self as ModemTrait>::new()
+    }
 }
 
 impl ModemTrait for XModem {
@@ -43,226 +268,1339 @@ impl ModemTrait for XModem {
     {
         Self {
             max_errors: 16,
-            pad_byte: 0x1a,
+            max_block_retries: 0,
+            max_initial_errors: 16,
+            pad_policy: PadPolicy::Byte(0x1a),
             block_length: BlockLengthKind::Standard,
-            checksum_mode: ChecksumKind::Standard,
-            errors: 0,
+            poll_count: 10,
+            first_block: 1,
+            resumable: true,
+            force_block_len: None,
+            init_byte_override: None,
+            receive_checksum: None,
+            max_recv_bytes: None,
+            max_unrecognized: None,
+            use_crc_ccitt: false,
+            crc_fn: None,
+            purge_on_start: false,
+            can_threshold: 2,
+            abt_threshold: 2,
+            event_sink: None,
+            byte_ready: None,
+            inter_block_delay: None,
+            yield_hook: None,
+            state: XModemState::default(),
         }
     }
+
+    /// XMODEM can resume with `send_from` and `recv_from`, as long as both
+    /// sides agree on the block to resume from, unless `resumable` is off.
+    fn supports_resume(&self) -> bool {
+        self.resumable
+    }
 }
 
-impl XModemTrait for XModem {
-    fn send<D, R>(&mut self, dev: &mut D, inp: &mut R) -> ModemResult<()>
+/// The number of consecutive `EOT`s met with silence after which the sender
+/// assumes the receiver already acknowledged the end of the transmission.
+/// The send then succeeds, but reports `ModemEvent::EotUnacknowledged`.
+pub const SILENT_EOT_RETRIES: u32 = 3;
+
+/// The most bytes the sender reads at once when it waits for a response. See
+/// `XModem::read_response`.
+const RESPONSE_READ_LEN: usize = 16;
+
+/// The number of stray bytes before the first block that count as one error
+/// when receiving: the length of the largest block.
+const STRAY_BYTES_PER_ERROR: u32 = BlockLengthKind::OneK as u32 + 5;
+
+/// Detects a request to cancel the transfer: a burst of `CAN` bytes.
+/// `CAN2`, a `CAN` with the high bit set, counts as one too.
+///
+/// The bytes received are fed to the detector, and only back-to-back `CAN`s
+/// count: any other byte in between, whether line noise, data or another
+/// control byte, starts the count over. `XModem::abort` and most other
+/// implementations send a burst of 8, so even a burst split by noise, e.g.
+/// `CAN <noise> CAN CAN`, still cancels with the default threshold of 2,
+/// while two `CAN`s scattered through data don't.
+///
+/// Some terminal programs let the user abort a transfer by typing `a` or `A`
+/// (`ABT`) instead, which is detected the same way once enabled with
+/// `with_abt_threshold`: the `ABT`s have to be back to back too, so text
+/// such as `banana` doesn't cancel.
+#[derive(Debug, Copy, Clone)]
+pub struct CanDetector {
+    threshold: u32,
+    count: u32,
+    abt_threshold: u32,
+    abt_count: u32,
+}
+
+impl CanDetector {
+    /// Creates a detector that triggers after `threshold` `CAN` bytes.
+    pub const fn new(threshold: u32) -> Self {
+        Self {
+            threshold,
+            count: 0,
+            abt_threshold: 0,
+            abt_count: 0,
+        }
+    }
+
+    /// Also triggers after `threshold` `ABT` bytes, or never if it is 0.
+    pub const fn with_abt_threshold(mut self, threshold: u32) -> Self {
+        self.abt_threshold = threshold;
+        self
+    }
+
+    /// Feeds a received byte to the detector, returning `true` once the
+    /// transfer should be canceled.
+    pub fn feed(&mut self, byte: u8) -> bool {
+        if byte.to_ascii_lowercase() == Consts::ABT.into() {
+            self.count = 0;
+            self.abt_count += 1;
+            return self.abt_threshold > 0
+                && self.abt_count >= self.abt_threshold;
+        }
+        self.abt_count = 0;
+        match Consts::from(byte) {
+            Consts::CAN | Consts::CAN2 => self.count += 1,
+            _ => self.count = 0,
+        }
+        self.count >= self.threshold
+    }
+}
+
+impl Default for CanDetector {
+    fn default() -> Self {
+        Self::new(2)
+    }
+}
+
+/// Fills in the header and checksum of the block in `buf`, whose data is at
+/// `buf[3..]`, and returns the frame's length. `crc_fn` replaces the CRC of
+/// the CRC modes, if given.
+fn frame_block(
+    buf: &mut [u8],
+    length: BlockLengthKind,
+    num: PacketNumber,
+    checksum: ChecksumKind,
+    crc_fn: Option<fn(&[u8]) -> u16>,
+) -> usize {
+    let block_length = length as usize;
+
+    buf[0] = length.header_byte();
+    buf[1] = num.0;
+    buf[2] = num.complement();
+
+    let data = &buf[3..3 + block_length];
+    let crc = match (checksum, crc_fn) {
+        (ChecksumKind::Standard, _) => {
+            buf[3 + block_length] = calc_checksum(data);
+            return block_length + 4;
+        }
+        (_, Some(crc_fn)) => crc_fn(data),
+        (ChecksumKind::Crc16, None) => calc_crc(data),
+        (ChecksumKind::Crc16Ccitt, None) => calc_crc_ccitt(data),
+    };
+    // XMODEM sends the CRC most significant byte first.
+    buf[3 + block_length] = ((crc >> 8) & 0xFF) as u8;
+    buf[4 + block_length] = (&crc & 0xFF) as u8;
+    block_length + 5
+}
+
+/// Checks a channel before a real transfer, by sending a block and reading it
+/// back.
+///
+/// This requires a loopback: the channel's output must be tied to its input,
+/// either physically (e.g. TX wired to RX) or in the driver. Every byte of a
+/// 128-byte CRC block is expected to come back unchanged, which catches bad
+/// wiring, mismatched line settings and transports that mangle binary data.
+/// The first byte that differs is reported as `ModemError::UnexpectedByte`.
+pub fn self_test<D: Read + Write>(dev: &mut D) -> ModemResult<()> {
+    let mut frame = [0u8; BlockLengthKind::Standard as usize + 5];
+    for (i, b) in frame[3..].iter_mut().enumerate() {
+        *b = i as u8;
+    }
+    let length = frame_block(
+        &mut frame,
+        BlockLengthKind::Standard,
+        PacketNumber(1),
+        ChecksumKind::Crc16,
+        None,
+    );
+    put_bytes(dev, &frame[..length])?;
+
+    for &expected in &frame[..length] {
+        let got = get_byte_timeout(dev)?.ok_or(ModemError::Timeout)?;
+        if got != expected {
+            return Err(ModemError::UnexpectedByte { got, expected });
+        }
+    }
+    Ok(())
+}
+
+/// Cancels a transfer when dropped, unless it has been disarmed.
+///
+/// `TransferGuard` wraps the communication channel for the duration of a
+/// transfer. If it goes out of scope while still armed (on an error, an early
+/// return or a panic), it sends the `CAN` sequence from `XModem::abort`, so the
+/// other party is not left waiting. Call `disarm` once the transfer has
+/// completed successfully.
+#[derive(Debug)]
+pub struct TransferGuard<'a, D: Write> {
+    dev: &'a mut D,
+    armed: bool,
+}
+
+impl<'a, D: Write> TransferGuard<'a, D> {
+    /// Wraps `dev`, arming the guard.
+    pub fn new(dev: &'a mut D) -> Self {
+        Self { dev, armed: true }
+    }
+
+    /// Disarms the guard, so dropping it no longer cancels the transfer.
+    pub fn disarm(&mut self) {
+        self.armed = false;
+    }
+}
+
+impl<D: Read + Write> Read for TransferGuard<'_, D> {
+    fn read(&mut self, buf: &mut [u8]) -> core2::io::Result<usize> {
+        self.dev.read(buf)
+    }
+}
+
+impl<D: Write> Write for TransferGuard<'_, D> {
+    fn write(&mut self, buf: &[u8]) -> core2::io::Result<usize> {
+        self.dev.write(buf)
+    }
+
+    fn flush(&mut self) -> core2::io::Result<()> {
+        self.dev.flush()
+    }
+}
+
+impl<D: Write> Drop for TransferGuard<'_, D> {
+    fn drop(&mut self) {
+        if self.armed {
+            // Nothing can be done about a failure here, the transfer is over.
+            let _ = XModem::abort(self.dev);
+        }
+    }
+}
+
+/// Closures called as a transfer progresses, for `XModem::send_with_hooks`
+/// and `XModem::receive_with_hooks`.
+///
+/// Each does the same as the `XModem` setting of the same name, but can
+/// capture state, e.g. to collect the events or count the blocks. They are
+/// borrowed for a single transfer rather than stored in the settings, so
+/// that `XModem` stays `Copy`. If both a setting and a closure are given,
+/// both are called.
+#[derive(Default)]
+pub struct TransferHooks<'a> {
+    /// Called with each protocol event. See `XModem::event_sink`.
+    pub event_sink: Option<&'a mut dyn FnMut(ModemEvent)>,
+    /// Called once per block or byte read. See `XModem::yield_hook`.
+    pub yield_hook: Option<&'a mut dyn FnMut()>,
+    /// Called after each block is acknowledged. See
+    /// `XModem::inter_block_delay`.
+    pub inter_block_delay: Option<&'a mut dyn FnMut()>,
+    /// Returns whether a byte can be read without blocking. See
+    /// `XModem::byte_ready`.
+    pub byte_ready: Option<&'a mut dyn FnMut() -> bool>,
+}
+
+impl core::fmt::Debug for TransferHooks<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("TransferHooks")
+            .field("event_sink", &self.event_sink.is_some())
+            .field("yield_hook", &self.yield_hook.is_some())
+            .field("inter_block_delay", &self.inter_block_delay.is_some())
+            .field("byte_ready", &self.byte_ready.is_some())
+            .finish()
+    }
+}
+
+impl XModem {
+    /// Creates an `XModem` that receives in CRC-16 mode, polling the sender
+    /// with `C`, whatever checksum is passed to `receive`.
+    ///
+    /// When sending, the receiver still dictates the mode: a sender created
+    /// with this preset falls back to the additive checksum if the receiver
+    /// asks for it.
+    pub fn crc() -> Self {
+        Self {
+            receive_checksum: Some(ChecksumKind::Crc16),
+            ..Self::new()
+        }
+    }
+
+    /// Creates an `XModem` that receives with the additive checksum, polling
+    /// the sender with `NAK`, whatever checksum is passed to `receive`.
+    ///
+    /// As with `crc`, this only affects receiving.
+    pub fn standard() -> Self {
+        Self {
+            receive_checksum: Some(ChecksumKind::Standard),
+            ..Self::new()
+        }
+    }
+
+    /// Returns the byte the last block's padding starts with: the byte of
+    /// `PadPolicy::Byte`, or the `^Z` of `PadPolicy::CtrlZTerminated`.
+    #[deprecated(note = "use the `pad_policy` field")]
+    pub fn pad_byte(&self) -> u8 {
+        match self.pad_policy {
+            PadPolicy::Byte(b) => b,
+            PadPolicy::CtrlZTerminated => 0x1a,
+        }
+    }
+
+    /// Pads the last block with `byte`, i.e. sets `pad_policy` to
+    /// `PadPolicy::Byte(byte)`. This replaces the `pad_byte` field.
+    #[deprecated(note = "use the `pad_policy` field")]
+    pub fn set_pad_byte(&mut self, byte: u8) {
+        self.pad_policy = PadPolicy::Byte(byte);
+    }
+
+    /// Clears the state left over from the previous transfer: the error
+    /// counters, the statistics and the negotiated checksum mode. The settings
+    /// are kept, so a long-lived instance can be reused. Every transfer does
+    /// this when it starts.
+    pub fn reset(&mut self) {
+        self.state = XModemState::default();
+    }
+
+    /// Returns the statistics of the last transfer, or of the one in
+    /// progress, whether or not it succeeded.
+    pub fn stats(&self) -> TransferStats {
+        self.state.stats
+    }
+
+    /// Returns the state of the last transfer, or of the one in progress.
+    pub fn state(&self) -> &XModemState {
+        &self.state
+    }
+
+    /// Returns the number of bytes sending `payload_len` bytes puts on the
+    /// line, e.g. to show the progress of a transfer as a share of the total.
+    ///
+    /// This counts what the sender writes when nothing goes wrong: each
+    /// block's 3-byte header, its data padded to `block_length` (or to 128
+    /// bytes for a short final block of an XMODEM-1k transfer), its checksum
+    /// (1 byte, or 2 for a CRC), and the final `EOT`. The checksum mode is
+    /// chosen by the receiver, so it has to be given. Retries, the
+    /// receiver's responses, and the second `EOT` of receivers that NAK the
+    /// first one are not included.
+    pub fn wire_size(&self, payload_len: u64, checksum: ChecksumKind) -> u64 {
+        let overhead = match checksum {
+            ChecksumKind::Standard => 4,
+            ChecksumKind::Crc16 | ChecksumKind::Crc16Ccitt => 5,
+        };
+        let block_length = self.block_length as u64;
+        let short = BlockLengthKind::Standard as u64;
+
+        let full_blocks = payload_len / block_length;
+        let last_block = match payload_len % block_length {
+            0 => 0,
+            rest if rest <= short => short + overhead,
+            _ => block_length + overhead,
+        };
+        full_blocks * (block_length + overhead) + last_block + 1
+    }
+
+    /// Sends the blocks of `inp`, numbering the first one `start_block`.
+    fn send_blocks<D, R>(
+        &mut self,
+        dev: &mut D,
+        inp: &mut R,
+        buf: &mut [u8],
+        start_block: u32,
+        hooks: &mut TransferHooks<'_>,
+    ) -> ModemResult<()>
     where
         D: Read + Write,
         R: Read,
     {
-        self.errors = 0;
+        let block_length = self.block_length as usize;
+        let buff = buf.get_mut(..block_length + 5).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                "scratch buffer must be at least block_length + 5 bytes",
+            )
+        })?;
 
-        self.init_send(dev)?;
+        let mut cancel = self.cancel_detector();
+        let mut block_num = start_block.wrapping_sub(1);
+        loop {
+            self.yield_now(hooks);
 
-        self.send_stream(dev, inp)?;
+            // Drain whatever arrived since the last block was acknowledged,
+            // so that it isn't taken as the response to the next one.
+            while self.byte_ready(hooks) {
+                let c = get_byte(dev)?;
+                if cancel.feed(c) {
+                    self.emit(hooks, ModemEvent::Canceled);
+                    return Err(ModemError::Canceled);
+                }
+                self.emit(hooks, ModemEvent::StaleResponse { byte: c });
+            }
 
-        self.finish_send(dev)?;
+            let block = &mut buff[3..3 + block_length];
+            let n = read_full(inp, block)?;
+            if n == 0 {
+                return Ok(());
+            }
+            self.pad_policy.pad(block, n);
 
-        Ok(())
+            block_num = block_num.wrapping_add(1);
+            let frame_length = self.encode_block(buff, block_num, n);
+
+            // Resend the same block until it is acknowledged.
+            let mut last_byte = None;
+            let mut retries = 0;
+            loop {
+                if retries > 0 {
+                    self.state.stats.retransmits += 1;
+                }
+                put_bytes(dev, &buff[..frame_length])?;
+                self.emit(hooks, ModemEvent::BlockSent { num: block_num });
+
+                // The receiver may have asked to start again before the first
+                // block arrived, e.g. on a timeout while the sender was slow
+                // to start, or `C` then `NAK` from one that alternates.
+                // `init_send` went with the first request, so the others are
+                // ignored rather than taken as a `NAK` of the block, which
+                // would put the two ends a block out of step. `C` is never a
+                // rejection; if the first block really was NAKed in CRC
+                // mode, it is resent after a timeout instead.
+                let response = loop {
+                    let c = self
+                        .read_response(dev, &mut cancel, hooks)?
+                        .map(Consts::from);
+                    let repeated_poll = match c {
+                        Some(Consts::CRC | Consts::CRC2 | Consts::CRC3) => true,
+                        Some(Consts::NAK) => {
+                            self.state.checksum_mode != ChecksumKind::Standard
+                                && block_num == start_block
+                                && retries == 0
+                        }
+                        _ => false,
+                    };
+                    match c {
+                        Some(c) if repeated_poll => {
+                            self.emit(
+                                hooks,
+                                ModemEvent::StaleResponse { byte: c.into() },
+                            );
+                        }
+                        c => break c,
+                    }
+                };
+                let timed_out = match response {
+                    // `ACK2` is an `ACK` with the high bit set.
+                    Some(Consts::ACK | Consts::ACK2) => {
+                        self.emit(
+                            hooks,
+                            ModemEvent::BlockAcked { num: block_num },
+                        );
+                        self.note_retries(block_num, retries);
+                        self.wait_between_blocks(hooks);
+                        break;
+                    }
+                    Some(c) => {
+                        if c == Consts::NAK {
+                            self.emit(hooks, ModemEvent::Nak);
+                        }
+                        last_byte = Some(c.into());
+                        false
+                    }
+                    None => {
+                        self.emit(hooks, ModemEvent::Timeout);
+                        true
+                    }
+                };
+
+                self.count_retry(&mut retries, timed_out);
+
+                if self.state.errors >= self.max_errors {
+                    self.note_retries(block_num, retries);
+                    return Err(self.exhausted_retries(last_byte, Consts::ACK));
+                }
+            }
+        }
     }
 
-    fn receive<D, W>(
+    /// Reads the receiver's response, or returns `None` on a timeout.
+    ///
+    /// The receiver sends a single byte and then waits for the sender, so
+    /// when one read of the channel returns several, only the last one is
+    /// the response. The others had arrived before it: stale responses, e.g.
+    /// a duplicated `ACK` or a repeated poll. They are discarded and reported
+    /// as `ModemEvent::StaleResponse`, rather than being read as the response
+    /// to the next block. Every byte is fed to `cancel`, so a `CAN` burst
+    /// that arrives in one read still cancels.
+    fn read_response<D: Read>(
+        &mut self,
+        dev: &mut D,
+        cancel: &mut CanDetector,
+        hooks: &mut TransferHooks<'_>,
+    ) -> ModemResult<Option<u8>> {
+        let mut buf = [0u8; RESPONSE_READ_LEN];
+        let n = match get_bytes(dev, &mut buf) {
+            Ok(n) => n,
+            Err(err) if err.kind() == ErrorKind::TimedOut => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        for (i, &c) in buf[..n].iter().enumerate() {
+            if cancel.feed(c) {
+                self.emit(hooks, ModemEvent::Canceled);
+                return Err(ModemError::Canceled);
+            }
+            if i + 1 < n {
+                self.emit(hooks, ModemEvent::StaleResponse { byte: c });
+            }
+        }
+        Ok(Some(buf[n - 1]))
+    }
+
+    /// Receives the blocks of a transmission, passing those numbered
+    /// `resume_block` or later to `on_block`. Earlier blocks are acknowledged
+    /// and discarded. The first block may be `first_block`, from a sender
+    /// that starts over, or `resume_block`, from one resuming with
+    /// `send_from`.
+    fn recv_blocks_from<D, F>(
         &mut self,
         dev: &mut D,
-        out: &mut W,
         checksum: ChecksumKind,
+        resume_block: u8,
+        hooks: &mut TransferHooks<'_>,
+        mut on_block: F,
     ) -> ModemResult<()>
     where
         D: Read + Write,
-        W: Write,
+        F: FnMut(&[u8]) -> ModemResult<()>,
     {
-        self.errors = 0;
-        self.checksum_mode = checksum;
+        self.reset();
+        self.state.checksum_mode = self.receive_checksum.unwrap_or(checksum);
+
+        if self.purge_on_start {
+            purge(dev)?;
+        }
+
+        let mut guard = TransferGuard::new(dev);
+        let dev = &mut guard;
 
-        dev.write_all(&[match self.checksum_mode {
-            ChecksumKind::Standard => Consts::NAK.into(),
-            ChecksumKind::Crc16 => Consts::CRC.into(),
-        }])?;
+        let poll = self.poll_byte();
+        put_bytes(dev, &[poll])?;
+        let mut polls = 1;
 
-        let mut packet_num: u8 = 1;
+        // The rest of a block after its header byte: the block number and its
+        // complement, the data, and a checksum of up to 2 bytes.
+        let mut frame = [0u8; BlockLengthKind::OneK as usize + 4];
+        let mut packet_num = PacketNumber(self.first_block);
+        // The number of the expected block, counted from the start of the
+        // file rather than wrapping.
+        let mut index = u32::from(self.first_block);
+        let mut received_block = false;
+        let mut stray = 0;
+        // Skipping stray bytes before the first block. They are data, so
+        // CANs and ABTs among them don't cancel.
+        let mut resyncing = false;
+        // Consecutive bytes that were neither a header nor a control byte.
+        let mut unrecognized = 0;
+        let mut started = false;
+        let mut eot_seen = false;
+        let mut blocks = 0u32;
+        let mut retries = 0;
+        let mut cancel = self.cancel_detector();
         loop {
-            match get_byte_timeout(dev)?.map(Consts::from) {
-                bt @ Some(Consts::SOH | Consts::STX) => {
+            self.yield_now(hooks);
+
+            let byte = get_byte_timeout(dev)?;
+            let run = core::mem::take(&mut unrecognized);
+            if !resyncing && byte.is_some_and(|c| cancel.feed(c)) {
+                self.emit(hooks, ModemEvent::Canceled);
+                return Err(ModemError::Canceled);
+            }
+            match byte.map(Consts::from) {
+                Some(c @ (Consts::SOH | Consts::STX)) => {
+                    started = true;
+                    eot_seen = false;
+                    resyncing = false;
                     // Handle next packet
-                    let packet_size = match bt {
-                        Some(Consts::SOH) => 128,
-                        Some(Consts::STX) => 1024,
-                        _ => 0, // Why does the compiler need this?
-                    };
-                    let pnum = get_byte(dev)?; // specified packet number
-                    let pnum_1c = get_byte(dev)?; // same, 1's complemented
-                                                  // We'll respond with cancel later if the packet number is wrong
+                    let packet_size = self.block_data_len(c.into());
+                    let checksum_size = self.checksum_len();
+                    // Read the whole block at once, rather than a byte at a
+                    // time, to save calls on channels where reads are costly.
+                    let frame = &mut frame[..2 + packet_size + checksum_size];
+                    dev.read_exact(frame)?;
+                    let (header, rest) = frame.split_at(2);
+                    let (data, sum) = rest.split_at(packet_size);
+                    let pnum = header[0]; // specified packet number
+                    let pnum_1c = header[1]; // same, 1's complemented
+                                             // We'll respond with cancel later if the packet number is wrong
+                    let valid_pnum = PacketNumber::validate(pnum, pnum_1c);
+                    if valid_pnum && !received_block && pnum == resume_block {
+                        packet_num = PacketNumber(resume_block);
+                        index = u32::from(resume_block);
+                    }
+                    // A sender that missed our ACK resends the previous block;
+                    // it is acknowledged again, but not written twice.
+                    let duplicate = valid_pnum
+                        && received_block
+                        && PacketNumber(pnum).next() == packet_num;
+                    let in_sequence = valid_pnum && packet_num.0 == pnum;
+                    // Before the first block, a bad header is more likely
+                    // stray data that looked like one; see below.
                     let cancel_packet =
-                        packet_num != pnum || (255 - pnum) != pnum_1c;
-                    let mut data: Vec<u8> = Vec::new();
-                    data.resize(packet_size, 0);
-                    dev.read_exact(&mut data)?;
-                    let success = match self.checksum_mode {
-                        ChecksumKind::Standard => {
-                            let recv_checksum = get_byte(dev)?;
-                            calc_checksum(&data) == recv_checksum
-                        }
-                        ChecksumKind::Crc16 => {
-                            let recv_checksum = (u16::from(get_byte(dev)?)
-                                << 8)
-                                + u16::from(get_byte(dev)?);
-                            calc_crc(&data) == recv_checksum
-                        }
-                    };
+                        received_block && !duplicate && !in_sequence;
+                    let success = self.check_block(data, sum);
 
                     if cancel_packet {
-                        dev.write_all(&[Consts::CAN.into()])?;
-                        dev.write_all(&[Consts::CAN.into()])?;
+                        // Dropping the guard sends the CAN sequence.
+                        self.emit(hooks, ModemEvent::Canceled);
                         return Err(ModemError::Canceled);
                     }
-                    if success {
-                        packet_num = packet_num.wrapping_add(1);
-                        dev.write_all(&[Consts::ACK.into()])?;
-                        out.write_all(&data)?;
+                    if success && duplicate {
+                        self.state.stats.retransmits += 1;
+                        put_bytes(dev, &[Consts::ACK.into()])?;
+                    } else if success && in_sequence {
+                        self.note_retries(u32::from(pnum), retries);
+                        packet_num = packet_num.next();
+                        received_block = true;
+                        blocks += 1;
+                        retries = 0;
+                        if index >= u32::from(resume_block) {
+                            // Dropping the guard sends the CAN sequence if
+                            // either fails, before the block is acknowledged.
+                            self.accept_bytes(data.len(), hooks)?;
+                            on_block(data)?;
+                        }
+                        index += 1;
+                        put_bytes(dev, &[Consts::ACK.into()])?;
+                        self.emit(
+                            hooks,
+                            ModemEvent::BlockAcked { num: blocks },
+                        );
                     } else {
-                        dev.write_all(&[Consts::NAK.into()])?;
-                        self.errors += 1;
+                        put_bytes(dev, &[Consts::NAK.into()])?;
+                        self.emit(hooks, ModemEvent::Nak);
+                        self.count_retry(&mut retries, false);
                     }
                 }
-                #[allow(non_snake_case)]
-                Some(_EOT) => {
+                Some(Consts::CAN | Consts::CAN2) if !resyncing => {
+                    // A lone CAN is treated as line noise.
+                    self.count_retry(&mut retries, false);
+                }
+                Some(c)
+                    if !resyncing
+                        && u8::from(c).to_ascii_lowercase()
+                            == Consts::ABT.into() =>
+                {
+                    // As is a lone ABT.
+                    self.count_retry(&mut retries, false);
+                }
+                Some(Consts::EOT) if !resyncing && !eot_seen => {
+                    // NAK the first EOT, in case it was a corrupted header;
+                    // a sender that really is done sends it again.
+                    put_bytes(dev, &[Consts::NAK.into()])?;
+                    eot_seen = true;
+                }
+                Some(Consts::EOT) if !resyncing => {
                     // End of file
-                    dev.write_all(&[Consts::ACK.into()])?;
+                    put_bytes(dev, &[Consts::ACK.into()])?;
                     break;
                 }
+                Some(c) if !received_block => {
+                    // The sender may have started before our poll, so we
+                    // joined in the middle of a block. Skip to the next
+                    // header, counting a block's worth of bytes as one error.
+                    resyncing = true;
+                    if let Consts::Unknown(c) = c {
+                        unrecognized = self.unrecognized_byte(run, c, hooks)?;
+                    }
+                    stray += 1;
+                    if stray % STRAY_BYTES_PER_ERROR == 0 {
+                        self.count_retry(&mut retries, false);
+                    }
+                }
+                Some(c) => {
+                    if let Consts::Unknown(c) = c {
+                        unrecognized = self.unrecognized_byte(run, c, hooks)?;
+                    }
+                    self.count_retry(&mut retries, false);
+                }
+                None if !started => {
+                    // Keep polling until the sender starts, in case our
+                    // first poll was lost.
+                    self.emit(hooks, ModemEvent::Timeout);
+                    if polls >= self.poll_count {
+                        return Err(ModemError::Timeout);
+                    }
+                    put_bytes(dev, &[poll])?;
+                    polls += 1;
+                }
                 None => {
-                    self.errors += 1;
+                    self.emit(hooks, ModemEvent::Timeout);
+                    self.count_retry(&mut retries, true);
                 }
             }
-            if self.errors >= self.max_errors {
-                dev.write_all(&[Consts::CAN.into()])?;
-                return Err(ModemError::ExhaustedRetries {
-                    errors: Box::from(self.errors),
-                });
+            if self.state.errors >= self.max_errors {
+                self.note_retries(u32::from(packet_num.0), retries);
+                return Err(self.too_many_errors());
             }
         }
+        dev.disarm();
         Ok(())
     }
 
-    fn init_send<D>(&mut self, dev: &mut D) -> ModemResult<()>
+    /// Frames the `n` bytes of data at `buf[3..]` as block `block_num`,
+    /// filling in the header and checksum, and returns the frame's length.
+    ///
+    /// The rest of the block must already be padded.
+    pub(crate) fn encode_block(
+        &self,
+        buf: &mut [u8],
+        block_num: u32,
+        n: usize,
+    ) -> usize {
+        // XMODEM-1k senders fall back to a 128-byte block when little
+        // enough data remains, to avoid sending mostly padding.
+        let length = match self.block_length {
+            BlockLengthKind::OneK
+                if n <= BlockLengthKind::Standard as usize =>
+            {
+                BlockLengthKind::Standard
+            }
+            length => length,
+        };
+
+        frame_block(
+            buf,
+            length,
+            PacketNumber((block_num & 0xFF) as u8),
+            self.state.checksum_mode,
+            self.crc_fn,
+        )
+    }
+
+    /// Counts `len` more bytes received, failing if that takes the total over
+    /// `max_recv_bytes`.
+    pub(crate) fn accept_bytes(
+        &mut self,
+        len: usize,
+        hooks: &mut TransferHooks<'_>,
+    ) -> ModemResult<()> {
+        let received = self.state.received.saturating_add(len as u64);
+        if let Some(limit) = self.max_recv_bytes {
+            if received > limit {
+                self.emit(hooks, ModemEvent::Canceled);
+                return Err(ModemError::SizeLimitExceeded { limit });
+            }
+        }
+        self.state.received = received;
+        Ok(())
+    }
+
+    /// Returns the length of the data in a block that starts with `header`,
+    /// or with `force_block_len` if it is set.
+    pub(crate) fn block_data_len(&self, header: u8) -> usize {
+        self.force_block_len
+            .or_else(|| BlockLengthKind::from_header_byte(header))
+            .map_or(0, |length| length as usize)
+    }
+
+    /// Returns the number of check bytes after a block's data in the current
+    /// checksum mode.
+    pub(crate) fn checksum_len(&self) -> usize {
+        match self.state.checksum_mode {
+            // A single byte, even after a 1024-byte block.
+            ChecksumKind::Standard => 1,
+            ChecksumKind::Crc16 | ChecksumKind::Crc16Ccitt => 2,
+        }
+    }
+
+    /// Returns whether `sum`, the `checksum_len` bytes after a block's
+    /// `data`, match it in the current checksum mode.
+    pub(crate) fn check_block(&self, data: &[u8], sum: &[u8]) -> bool {
+        match self.state.checksum_mode {
+            ChecksumKind::Standard => calc_checksum(data) == sum[0],
+            ChecksumKind::Crc16 | ChecksumKind::Crc16Ccitt => {
+                // Most significant byte first.
+                self.calc_crc(data) == u16::from_be_bytes([sum[0], sum[1]])
+            }
+        }
+    }
+
+    /// Counts an unrecognized byte after a run of `run` of them, failing if
+    /// the run has reached `max_unrecognized`. Returns the new run length.
+    pub(crate) fn unrecognized_byte(
+        &mut self,
+        run: u32,
+        byte: u8,
+        hooks: &mut TransferHooks<'_>,
+    ) -> ModemResult<u32> {
+        let run = run + 1;
+        if self.max_unrecognized.is_some_and(|max| run >= max) {
+            self.emit(hooks, ModemEvent::Canceled);
+            return Err(ModemError::LineError { unexpected: byte });
+        }
+        Ok(run)
+    }
+
+    /// Calls the yield hooks, if there are any.
+    pub(crate) fn yield_now(&self, hooks: &mut TransferHooks<'_>) {
+        if let Some(hook) = self.yield_hook {
+            hook();
+        }
+        if let Some(hook) = &mut hooks.yield_hook {
+            hook();
+        }
+    }
+
+    /// Calls the inter-block delays, if there are any.
+    fn wait_between_blocks(&self, hooks: &mut TransferHooks<'_>) {
+        if let Some(delay) = self.inter_block_delay {
+            delay();
+        }
+        if let Some(delay) = &mut hooks.inter_block_delay {
+            delay();
+        }
+    }
+
+    /// Returns whether `byte_ready` or its hook says a byte is waiting. Without
+    /// either, there is no telling, so this returns `false`.
+    fn byte_ready(&self, hooks: &mut TransferHooks<'_>) -> bool {
+        if let Some(ready) = self.byte_ready {
+            if ready() {
+                return true;
+            }
+        }
+        hooks.byte_ready.as_mut().is_some_and(|ready| ready())
+    }
+
+    /// Returns the byte the receiver sends to start the transmission in the
+    /// current checksum mode.
+    pub(crate) fn poll_byte(&self) -> u8 {
+        self.init_byte_override
+            .unwrap_or(match self.state.checksum_mode {
+                ChecksumKind::Standard => Consts::NAK.into(),
+                ChecksumKind::Crc16 | ChecksumKind::Crc16Ccitt => {
+                    Consts::CRC.into()
+                }
+            })
+    }
+
+    /// Creates the cancel detector for a transfer with these settings.
+    pub(crate) fn cancel_detector(&self) -> CanDetector {
+        CanDetector::new(self.can_threshold)
+            .with_abt_threshold(self.abt_threshold)
+    }
+
+    /// Records in the retry log that block `block_num` needed `retries`
+    /// retries, if it needed any.
+    pub(crate) fn note_retries(&mut self, block_num: u32, retries: u32) {
+        if retries == 0 {
+            return;
+        }
+        let log = &mut self.state.retry_log;
+        if self.state.retry_log_len == log.len() {
+            log.copy_within(1.., 0);
+            self.state.retry_log_len -= 1;
+        }
+        log[self.state.retry_log_len] = (
+            (block_num & 0xFF) as u8,
+            u8::try_from(retries).unwrap_or(u8::MAX),
+        );
+        self.state.retry_log_len += 1;
+    }
+
+    /// Counts a retry of the current block, against `max_errors` once the
+    /// block has used up its `max_block_retries`.
+    pub(crate) fn count_retry(&mut self, retries: &mut u32, timed_out: bool) {
+        *retries += 1;
+        if *retries > self.max_block_retries {
+            self.state.errors += 1;
+            if timed_out {
+                self.state.timeouts += 1;
+            }
+        }
+    }
+
+    /// Passes `event` to the event sinks, if there are any.
+    /// Also updates the statistics that follow from it.
+    pub(crate) fn emit(
+        &mut self,
+        hooks: &mut TransferHooks<'_>,
+        event: ModemEvent,
+    ) {
+        match event {
+            ModemEvent::BlockAcked { .. } => self.state.stats.blocks += 1,
+            ModemEvent::Nak => self.state.stats.crc_failures += 1,
+            ModemEvent::Timeout => self.state.stats.timeouts += 1,
+            ModemEvent::EotUnacknowledged => {
+                self.state.stats.eot_unacknowledged = true;
+            }
+            ModemEvent::BlockSent { .. }
+            | ModemEvent::Canceled
+            | ModemEvent::StaleResponse { .. } => (),
+        }
+        if let Some(sink) = self.event_sink {
+            sink(event);
+        }
+        if let Some(sink) = &mut hooks.event_sink {
+            sink(event);
+        }
+    }
+
+    /// Calculates the CRC for the current checksum mode, or with `crc_fn`.
+    pub(crate) fn calc_crc(&self, data: &[u8]) -> u16 {
+        if let Some(crc_fn) = self.crc_fn {
+            return crc_fn(data);
+        }
+        match self.state.checksum_mode {
+            ChecksumKind::Crc16Ccitt => calc_crc_ccitt(data),
+            _ => calc_crc(data),
+        }
+    }
+
+    /// Builds the error returned once `max_errors` is reached while waiting
+    /// for `expected`, naming the last unexpected byte if there was one.
+    pub(crate) fn exhausted_retries(
+        &self,
+        last_byte: Option<u8>,
+        expected: Consts,
+    ) -> ModemError {
+        match last_byte {
+            Some(got) if self.state.timeouts < self.state.errors => {
+                ModemError::UnexpectedByte {
+                    got,
+                    expected: expected.into(),
+                }
+            }
+            _ => self.too_many_errors(),
+        }
+    }
+
+    /// Builds the error returned once `max_errors` is reached: `Timeout` if
+    /// every error was a timeout, as the other party is probably not there.
+    pub(crate) fn too_many_errors(&self) -> ModemError {
+        if self.state.timeouts >= self.state.errors {
+            ModemError::Timeout
+        } else {
+            ModemError::ExhaustedRetries {
+                errors: self.state.errors,
+            }
+        }
+    }
+}
+
+impl XModem {
+    /// Sends the whole of `inp` as `XModemTrait::send` does, calling `hooks`
+    /// as the transfer progresses.
+    pub fn send_with_hooks<D, R>(
+        &mut self,
+        dev: &mut D,
+        inp: &mut R,
+        mut hooks: TransferHooks<'_>,
+    ) -> ModemResult<()>
     where
         D: Read + Write,
+        R: Read,
+    {
+        let mut buff = [0u8; BlockLengthKind::OneK as usize + 5];
+        let start_block = u32::from(self.first_block);
+        self.send_hooked(dev, inp, &mut buff, start_block, &mut hooks)
+    }
+
+    /// Receives a transmission into `out` as `XModemTrait::receive` does,
+    /// calling `hooks` as the transfer progresses.
+    pub fn receive_with_hooks<D, W>(
+        &mut self,
+        dev: &mut D,
+        out: &mut W,
+        checksum: ChecksumKind,
+        mut hooks: TransferHooks<'_>,
+    ) -> ModemResult<()>
+    where
+        D: Read + Write,
+        W: Write,
+    {
+        self.recv_blocks_from(
+            dev,
+            checksum,
+            self.first_block,
+            &mut hooks,
+            |block| Ok(out.write_all(block)?),
+        )
+    }
+
+    /// Runs a whole send, numbering the first block `start_block` and using
+    /// `buf` as scratch space.
+    fn send_hooked<D, R>(
+        &mut self,
+        dev: &mut D,
+        inp: &mut R,
+        buf: &mut [u8],
+        start_block: u32,
+        hooks: &mut TransferHooks<'_>,
+    ) -> ModemResult<()>
+    where
+        D: Read + Write,
+        R: Read,
     {
-        let mut cancels = 0u32;
+        self.reset();
+
+        if self.purge_on_start {
+            purge(dev)?;
+        }
+
+        let mut guard = TransferGuard::new(dev);
+        let dev = &mut guard;
+
+        self.init_send_hooked(dev, hooks)?;
+
+        self.send_blocks(dev, inp, buf, start_block, hooks)?;
+
+        self.finish_send_hooked(dev, hooks)?;
+
+        dev.disarm();
+        Ok(())
+    }
+
+    /// `init_send`, calling `hooks` as well as the hook settings.
+    fn init_send_hooked<D>(
+        &mut self,
+        dev: &mut D,
+        hooks: &mut TransferHooks<'_>,
+    ) -> ModemResult<()>
+    where
+        D: Read + Write,
+    {
+        let mut cancel = self.cancel_detector();
+        let mut timeouts = 0;
         loop {
-            if let Some(c) = get_byte_timeout(dev)?.map(Consts::from) {
-                match c {
-                    Consts::NAK => {
-                        self.checksum_mode = ChecksumKind::Standard;
-                        return Ok(());
-                    }
-                    Consts::CRC => {
-                        self.checksum_mode = ChecksumKind::Crc16;
-                        return Ok(());
+            // A receiver that was kept waiting has polled once per timeout,
+            // and each poll is queued. The first one decides the mode; the
+            // rest, and any others sent before the first block, are ignored,
+            // rather than being taken as a `NAK` of it, which would put the
+            // two ends a block out of step.
+            let mut buf = [0u8; RESPONSE_READ_LEN];
+            let n = match get_bytes(dev, &mut buf) {
+                Ok(n) => n,
+                Err(err) if err.kind() == ErrorKind::TimedOut => 0,
+                Err(err) => return Err(err.into()),
+            };
+            let mut mode = None;
+            for &c in &buf[..n] {
+                if cancel.feed(c) {
+                    self.emit(hooks, ModemEvent::Canceled);
+                    return Err(ModemError::Canceled);
+                }
+                if mode.is_some() {
+                    self.emit(hooks, ModemEvent::StaleResponse { byte: c });
+                    continue;
+                }
+                mode = match Consts::from(c) {
+                    Consts::NAK => Some(ChecksumKind::Standard),
+                    // `CRC2` and `CRC3` are the same request from receivers
+                    // on lines that set the high bit, e.g. for parity.
+                    Consts::CRC | Consts::CRC2 | Consts::CRC3 => {
+                        Some(if self.use_crc_ccitt {
+                            ChecksumKind::Crc16Ccitt
+                        } else {
+                            ChecksumKind::Crc16
+                        })
                     }
-                    Consts::CAN => {
-                        cancels += 1;
+                    _c => {
+                        self.state.initial_errors += 1;
+                        None
                     }
-                    _c => (),
-                }
+                };
             }
-
-            self.errors += 1;
-
-            if cancels >= 2 {
-                return Err(ModemError::Canceled);
+            if let Some(mode) = mode {
+                self.state.checksum_mode = mode;
+                return Ok(());
+            }
+            if n == 0 {
+                timeouts += 1;
+                self.state.initial_errors += 1;
             }
 
-            if self.errors >= self.max_errors {
+            if self.state.initial_errors >= self.max_initial_errors {
                 // FIXME: Removed a unused 'if let' here. To be re-added?
+                if timeouts == self.state.initial_errors {
+                    return Err(ModemError::Timeout);
+                }
                 return Err(ModemError::ExhaustedRetries {
-                    errors: Box::from(self.errors),
+                    errors: self.state.initial_errors,
                 });
             }
         }
     }
 
-    fn finish_send<D>(&mut self, dev: &mut D) -> ModemResult<()>
+    /// `finish_send`, calling `hooks` as well as the hook settings.
+    fn finish_send_hooked<D>(
+        &mut self,
+        dev: &mut D,
+        hooks: &mut TransferHooks<'_>,
+    ) -> ModemResult<()>
     where
         D: Read + Write,
     {
+        let mut cancel = self.cancel_detector();
+        let mut last_byte = None;
+        let mut silent_eots = 0;
+        let mut eot_naked = false;
         loop {
-            dev.write_all(&[Consts::EOT.into()])?;
+            put_bytes(dev, &[Consts::EOT.into()])?;
 
             if let Some(c) = get_byte_timeout(dev)? {
                 // Appease Clippy with this conditional black.
                 #[allow(clippy::redundant_else)]
-                if c == Consts::ACK.into() {
+                if matches!(Consts::from(c), Consts::ACK | Consts::ACK2) {
                     return Ok(());
                 }
-            };
+                if cancel.feed(c) {
+                    self.emit(hooks, ModemEvent::Canceled);
+                    return Err(ModemError::Canceled);
+                }
+                silent_eots = 0;
+                // Receivers may NAK the first EOT to make sure it wasn't a
+                // corrupted header, which isn't an error.
+                if c == Consts::NAK.into() && !eot_naked {
+                    eot_naked = true;
+                    continue;
+                }
+                last_byte = Some(c);
+            } else {
+                // Every block has been acknowledged, so if the receiver has
+                // gone quiet it most likely ACKed an earlier EOT that was lost,
+                // and has already finished.
+                silent_eots += 1;
+                if silent_eots >= SILENT_EOT_RETRIES {
+                    self.emit(hooks, ModemEvent::EotUnacknowledged);
+                    return Ok(());
+                }
+                self.state.timeouts += 1;
+            }
 
-            self.errors += 1;
+            self.state.errors += 1;
 
-            if self.errors >= self.max_errors {
-                return Err(ModemError::ExhaustedRetries {
-                    errors: Box::from(self.errors),
-                });
+            if self.state.errors >= self.max_errors {
+                return Err(self.exhausted_retries(last_byte, Consts::ACK));
             }
         }
     }
+}
 
-    fn send_stream<D, R>(&mut self, dev: &mut D, inp: &mut R) -> ModemResult<()>
+impl XModemTrait for XModem {
+    fn send<D, R>(&mut self, dev: &mut D, inp: &mut R) -> ModemResult<()>
     where
         D: Read + Write,
         R: Read,
     {
-        let mut block_num = 0u32;
-        loop {
-            let mut buff = vec![self.pad_byte; self.block_length as usize + 3];
-            let n = inp.read(&mut buff[3..])?;
-            if n == 0 {
-                return Ok(());
-            }
+        self.send_with_hooks(dev, inp, TransferHooks::default())
+    }
 
-            block_num += 1;
-            buff[0] = match self.block_length {
-                BlockLengthKind::Standard => Consts::SOH.into(),
-                BlockLengthKind::OneK => Consts::STX.into(),
-            };
-            buff[1] = (&block_num & 0xFF) as u8;
-            buff[2] = 0xFF - &buff[1];
+    fn send_with_buffer<D, R>(
+        &mut self,
+        dev: &mut D,
+        inp: &mut R,
+        buf: &mut [u8],
+    ) -> ModemResult<()>
+    where
+        D: Read + Write,
+        R: Read,
+    {
+        let start_block = u32::from(self.first_block);
+        let hooks = &mut TransferHooks::default();
+        self.send_hooked(dev, inp, buf, start_block, hooks)
+    }
 
-            match self.checksum_mode {
-                ChecksumKind::Standard => {
-                    let checksum = calc_checksum(&buff[3..]);
-                    buff.push(checksum);
-                }
-                ChecksumKind::Crc16 => {
-                    let crc = calc_crc(&buff[3..]);
-                    buff.push(((crc >> 8) & 0xFF) as u8);
-                    buff.push((&crc & 0xFF) as u8);
-                }
+    fn send_from<D, R>(
+        &mut self,
+        dev: &mut D,
+        inp: &mut R,
+        start_block: u32,
+    ) -> ModemResult<()>
+    where
+        D: Read + Write,
+        R: Read,
+    {
+        let mut buff = [0u8; BlockLengthKind::OneK as usize + 5];
+        let hooks = &mut TransferHooks::default();
+        self.send_hooked(dev, inp, &mut buff, start_block, hooks)
+    }
+
+    fn send_raw_block<D>(
+        &mut self,
+        dev: &mut D,
+        block_num: u8,
+        payload: &[u8],
+        checksum: ChecksumKind,
+    ) -> ModemResult<u8>
+    where
+        D: Read + Write,
+    {
+        let length = match payload.len() {
+            0..=128 => BlockLengthKind::Standard,
+            129..=1024 => BlockLengthKind::OneK,
+            length => {
+                return Err(ModemError::UnsupportedBlockLength { length })
             }
+        };
 
-            dev.write_all(&buff)?;
+        let mut buff = [0u8; BlockLengthKind::OneK as usize + 5];
+        buff[3..3 + payload.len()].copy_from_slice(payload);
+        self.pad_policy
+            .pad(&mut buff[3..3 + length as usize], payload.len());
+        let frame_length = frame_block(
+            &mut buff,
+            length,
+            PacketNumber(block_num),
+            checksum,
+            self.crc_fn,
+        );
+        put_bytes(dev, &buff[..frame_length])?;
 
-            if let Some(c) = get_byte_timeout(dev)? {
-                if c == Consts::ACK.into() {
-                    continue;
-                }
-                // TODO handle CAN bytes
-            }
+        get_byte_timeout(dev)?.ok_or(ModemError::Timeout)
+    }
+
+    fn send_broadcast<D, R>(
+        &mut self,
+        dev: &mut D,
+        inp: &mut R,
+        checksum: ChecksumKind,
+    ) -> ModemResult<()>
+    where
+        D: Write,
+        R: Read,
+    {
+        self.reset();
+        self.state.checksum_mode = checksum;
+        let hooks = &mut TransferHooks::default();
 
-            self.errors += 1;
+        let mut guard = TransferGuard::new(dev);
+        let dev = &mut guard;
 
-            if self.errors >= self.max_errors {
-                return Err(ModemError::ExhaustedRetries {
-                    errors: Box::from(self.errors),
-                });
+        let block_length = self.block_length as usize;
+        let mut buff = [0u8; BlockLengthKind::OneK as usize + 5];
+        let mut block_num = u32::from(self.first_block);
+        loop {
+            self.yield_now(hooks);
+
+            let block = &mut buff[3..3 + block_length];
+            let n = read_full(inp, block)?;
+            if n == 0 {
+                break;
             }
+            self.pad_policy.pad(block, n);
+
+            let frame_length = self.encode_block(&mut buff, block_num, n);
+            put_bytes(dev, &buff[..frame_length])?;
+            self.emit(hooks, ModemEvent::BlockSent { num: block_num });
+            self.wait_between_blocks(hooks);
+            block_num = block_num.wrapping_add(1);
         }
+        put_bytes(dev, &[Consts::EOT.into()])?;
+
+        dev.disarm();
+        Ok(())
+    }
+
+    fn receive<D, W>(
+        &mut self,
+        dev: &mut D,
+        out: &mut W,
+        checksum: ChecksumKind,
+    ) -> ModemResult<()>
+    where
+        D: Read + Write,
+        W: Write,
+    {
+        self.recv_blocks(dev, checksum, |block| Ok(out.write_all(block)?))
+    }
+
+    fn recv_blocks<D, F>(
+        &mut self,
+        dev: &mut D,
+        checksum: ChecksumKind,
+        on_block: F,
+    ) -> ModemResult<()>
+    where
+        D: Read + Write,
+        F: FnMut(&[u8]) -> ModemResult<()>,
+    {
+        let hooks = &mut TransferHooks::default();
+        self.recv_blocks_from(dev, checksum, self.first_block, hooks, on_block)
+    }
+
+    fn recv_from<D, W>(
+        &mut self,
+        dev: &mut D,
+        out: &mut W,
+        checksum: ChecksumKind,
+        first_expected_block: u8,
+    ) -> ModemResult<()>
+    where
+        D: Read + Write,
+        W: Write,
+    {
+        let hooks = &mut TransferHooks::default();
+        self.recv_blocks_from(
+            dev,
+            checksum,
+            first_expected_block,
+            hooks,
+            |block| Ok(out.write_all(block)?),
+        )
+    }
+
+    fn abort<D>(dev: &mut D) -> ModemResult<()>
+    where
+        D: Write,
+    {
+        send_can_sequence(dev)
+    }
+
+    fn init_send<D>(&mut self, dev: &mut D) -> ModemResult<()>
+    where
+        D: Read + Write,
+    {
+        self.init_send_hooked(dev, &mut TransferHooks::default())
+    }
+
+    fn finish_send<D>(&mut self, dev: &mut D) -> ModemResult<()>
+    where
+        D: Read + Write,
+    {
+        self.finish_send_hooked(dev, &mut TransferHooks::default())
+    }
+
+    fn send_stream<D, R>(&mut self, dev: &mut D, inp: &mut R) -> ModemResult<()>
+    where
+        D: Read + Write,
+        R: Read,
+    {
+        let mut buff = [0u8; BlockLengthKind::OneK as usize + 5];
+        self.send_stream_with_buffer(dev, inp, &mut buff)
+    }
+
+    fn send_stream_with_buffer<D, R>(
+        &mut self,
+        dev: &mut D,
+        inp: &mut R,
+        buf: &mut [u8],
+    ) -> ModemResult<()>
+    where
+        D: Read + Write,
+        R: Read,
+    {
+        let start_block = u32::from(self.first_block);
+        let hooks = &mut TransferHooks::default();
+        self.send_blocks(dev, inp, buf, start_block, hooks)
     }
 }
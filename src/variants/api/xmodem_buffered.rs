@@ -0,0 +1,83 @@
+use core::fmt;
+use core2::io::{Read, Result, Write};
+
+use crate::variants::xmodem::common::BlockLengthKind;
+
+/// The number of bytes a `BufferedChannel` holds by default: a whole
+/// XMODEM-1k block, with its header and CRC.
+pub const DEFAULT_BUFFER_CAPACITY: usize = BlockLengthKind::OneK as usize + 5;
+
+/// Reads ahead from a channel, so that a transfer makes one underlying read
+/// per block rather than one per byte.
+///
+/// The transfers read the header byte of each block on its own, then the rest
+/// of the block. That is two reads of a serial port, which is cheap, but on
+/// channels where every read is costly (e.g. XMODEM tunnelled over TCP) it
+/// adds up, and the control bytes in between are read a byte at a time too.
+/// Wrap the channel in a `BufferedChannel` and pass that to the transfer in
+/// its place: each read fills the buffer with whatever the channel has ready,
+/// up to `N` bytes, and later reads are served from it. The checksum is still
+/// computed by the transfer, over exactly the block's data.
+///
+/// Bytes that were read ahead only exist in the buffer, so keep the same
+/// `BufferedChannel` for the whole transfer. Writes are passed straight
+/// through.
+pub struct BufferedChannel<'a, D, const N: usize = DEFAULT_BUFFER_CAPACITY> {
+    dev: &'a mut D,
+    buf: [u8; N],
+    pos: usize,
+    len: usize,
+}
+
+impl<'a, D, const N: usize> BufferedChannel<'a, D, N> {
+    /// Wraps `dev`, with an empty buffer.
+    pub fn new(dev: &'a mut D) -> Self {
+        Self {
+            dev,
+            buf: [0; N],
+            pos: 0,
+            len: 0,
+        }
+    }
+
+    /// Returns the bytes that have been read from the channel but not yet
+    /// from the `BufferedChannel`.
+    pub fn buffered(&self) -> &[u8] {
+        &self.buf[self.pos..self.len]
+    }
+}
+
+impl<D: Read, const N: usize> Read for BufferedChannel<'_, D, N> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if self.pos == self.len {
+            // Nothing would be gained by copying a read this large.
+            if buf.len() >= N {
+                return self.dev.read(buf);
+            }
+            self.len = self.dev.read(&mut self.buf)?;
+            self.pos = 0;
+        }
+        let n = buf.len().min(self.len - self.pos);
+        buf[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+impl<D: Write, const N: usize> Write for BufferedChannel<'_, D, N> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.dev.write(buf)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.dev.flush()
+    }
+}
+
+impl<D, const N: usize> fmt::Debug for BufferedChannel<'_, D, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BufferedChannel")
+            .field("buffered", &self.buffered())
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,114 @@
+use crate::common::{
+    calc_checksum, calc_crc, calc_crc_ccitt, ModemError, ModemResult,
+    PacketNumber,
+};
+use core2::io::{Error, ErrorKind};
+
+use crate::variants::xmodem::{
+    common::{BlockLengthKind, ChecksumKind},
+    Consts,
+};
+
+/// A single frame decoded from a captured XMODEM byte stream.
+#[derive(Debug, Copy, Clone)]
+#[allow(variant_size_differences)] // `Data` only borrows its payload.
+pub enum Frame<'a> {
+    /// A data block.
+    Data {
+        /// The block number from the header.
+        block: u8,
+        /// Whether the block number matched its one's complement.
+        block_ok: bool,
+        /// The block's data, including any padding.
+        payload: &'a [u8],
+        /// Whether the block's checksum matched its data.
+        checksum_ok: bool,
+    },
+    /// End of transmission.
+    Eot,
+    /// Cancel: `CAN`, or `CAN2`.
+    Can,
+    /// Acknowledgement: `ACK`, or `ACK2`.
+    Ack,
+    /// Negative acknowledgement.
+    Nak,
+    /// A request to start the transmission in CRC mode: `C`, `CRC2` or `CRC3`.
+    Crc,
+    /// Any other byte.
+    Unknown(u8),
+}
+
+/// Decodes XMODEM frames from a captured byte stream, without a device.
+///
+/// This is intended for analysing serial logs. Both directions of the
+/// conversation may be interleaved in the capture; control bytes are reported
+/// as their own frames. A truncated final block is reported as an
+/// `ErrorKind::UnexpectedEof` I/O error, after which the iterator ends.
+#[derive(Debug, Copy, Clone)]
+pub struct XModemFrameDecoder<'a> {
+    data: &'a [u8],
+    checksum: ChecksumKind,
+}
+
+impl<'a> XModemFrameDecoder<'a> {
+    /// Decodes `data`, validating blocks with the given checksum mode.
+    pub fn new(data: &'a [u8], checksum: ChecksumKind) -> Self {
+        Self { data, checksum }
+    }
+}
+
+impl<'a> Iterator for XModemFrameDecoder<'a> {
+    type Item = ModemResult<Frame<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (&byte, rest) = self.data.split_first()?;
+
+        let Some(length) = BlockLengthKind::from_header_byte(byte) else {
+            self.data = rest;
+            // As on the line, the variants with the high bit set count too.
+            return Some(Ok(match Consts::from(byte) {
+                Consts::EOT => Frame::Eot,
+                Consts::CAN | Consts::CAN2 => Frame::Can,
+                Consts::ACK | Consts::ACK2 => Frame::Ack,
+                Consts::NAK => Frame::Nak,
+                Consts::CRC | Consts::CRC2 | Consts::CRC3 => Frame::Crc,
+                _ => Frame::Unknown(byte),
+            }));
+        };
+
+        let length = length as usize;
+        let checksum_length = match self.checksum {
+            ChecksumKind::Standard => 1,
+            ChecksumKind::Crc16 | ChecksumKind::Crc16Ccitt => 2,
+        };
+        if rest.len() < length + 2 + checksum_length {
+            self.data = &[];
+            return Some(Err(ModemError::Io(Error::new(
+                ErrorKind::UnexpectedEof,
+                "truncated block",
+            ))));
+        }
+
+        let (frame, rest) = rest.split_at(length + 2 + checksum_length);
+        self.data = rest;
+
+        let payload = &frame[2..2 + length];
+        let sum = &frame[2 + length..];
+        let checksum_ok = match self.checksum {
+            ChecksumKind::Standard => calc_checksum(payload) == sum[0],
+            ChecksumKind::Crc16 => {
+                calc_crc(payload) == u16::from_be_bytes([sum[0], sum[1]])
+            }
+            ChecksumKind::Crc16Ccitt => {
+                calc_crc_ccitt(payload) == u16::from_be_bytes([sum[0], sum[1]])
+            }
+        };
+
+        Some(Ok(Frame::Data {
+            block: frame[0],
+            block_ok: PacketNumber::validate(frame[0], frame[1]),
+            payload,
+            checksum_ok,
+        }))
+    }
+}
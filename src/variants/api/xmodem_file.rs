@@ -0,0 +1,53 @@
+extern crate std;
+
+use alloc::boxed::Box;
+use core::time::Duration;
+use serialport::SerialPort;
+use std::fs::File;
+use std::path::Path;
+
+use crate::common::{ModemError, ModemResult, ModemTrait, XModemTrait};
+use crate::variants::api::xmodem::XModem;
+use crate::variants::xmodem::common::ChecksumKind;
+
+/// The read timeout set on serial ports opened by `send_file` and
+/// `recv_file`.
+pub const SERIAL_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Opens `port` at `baud_rate`, with `SERIAL_TIMEOUT` as its read timeout.
+fn open_port(port: &str, baud_rate: u32) -> ModemResult<Box<dyn SerialPort>> {
+    serialport::new(port, baud_rate)
+        .timeout(SERIAL_TIMEOUT)
+        .open()
+        .map_err(|err| ModemError::Io(err.into()))
+}
+
+/// Sends the file at `path` over the serial port `port` (e.g.
+/// `/dev/ttyUSB0`), using XMODEM with the default settings.
+pub fn send_file<P: AsRef<Path>>(
+    port: &str,
+    baud_rate: u32,
+    path: P,
+) -> ModemResult<()> {
+    let mut file = File::open(path)?;
+    let mut dev = open_port(port, baud_rate)?;
+
+    XModem::new().send(&mut dev, &mut file)
+}
+
+/// Receives a file over the serial port `port` (e.g. `/dev/ttyUSB0`) into a
+/// new file at `path`, using XMODEM with the default settings.
+///
+/// The final block's padding is written to the file too, as XMODEM does not
+/// transmit the file's length.
+pub fn recv_file<P: AsRef<Path>>(
+    port: &str,
+    baud_rate: u32,
+    path: P,
+    checksum: ChecksumKind,
+) -> ModemResult<()> {
+    let mut file = File::create(path)?;
+    let mut dev = open_port(port, baud_rate)?;
+
+    XModem::new().receive(&mut dev, &mut file, checksum)
+}
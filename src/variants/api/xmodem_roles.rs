@@ -0,0 +1,212 @@
+use core::hash::Hasher;
+
+use crate::common::{ModemResult, XModemTrait};
+use core2::io::{Read, Write};
+
+use crate::variants::api::xmodem::{XModem, XModemState};
+use crate::variants::xmodem::common::ChecksumKind;
+
+impl XModem {
+    /// Turns these settings into an `XModemSender`, which can only send.
+    pub fn into_sender(self) -> XModemSender {
+        XModemSender { xmodem: self }
+    }
+
+    /// Turns these settings into an `XModemReceiver`, which can only
+    /// receive.
+    pub fn into_receiver(self) -> XModemReceiver {
+        XModemReceiver { xmodem: self }
+    }
+}
+
+/// An `XModem` that can only send, made with `XModem::into_sender`.
+///
+/// `XModem` keeps the state of one transfer at a time, whichever way it goes.
+/// Giving each end of a link its own role-specific value rules out e.g.
+/// starting a receive on the instance a send is using, and makes it clear
+/// from the type which way the data flows. The methods are those of
+/// `XModemTrait`, which documents them.
+#[derive(Debug, Copy, Clone)]
+pub struct XModemSender {
+    xmodem: XModem,
+}
+
+impl XModemSender {
+    /// Sends the whole of `inp`. See `XModemTrait::send`.
+    pub fn send<D: Read + Write, R: Read>(
+        &mut self,
+        dev: &mut D,
+        inp: &mut R,
+    ) -> ModemResult<()> {
+        self.xmodem.send(dev, inp)
+    }
+
+    /// See `XModemTrait::send_with_buffer`.
+    pub fn send_with_buffer<D: Read + Write, R: Read>(
+        &mut self,
+        dev: &mut D,
+        inp: &mut R,
+        buf: &mut [u8],
+    ) -> ModemResult<()> {
+        self.xmodem.send_with_buffer(dev, inp, buf)
+    }
+
+    /// See `XModemTrait::send_from`.
+    pub fn send_from<D: Read + Write, R: Read>(
+        &mut self,
+        dev: &mut D,
+        inp: &mut R,
+        start_block: u32,
+    ) -> ModemResult<()> {
+        self.xmodem.send_from(dev, inp, start_block)
+    }
+
+    /// See `XModemTrait::send_slice`.
+    pub fn send_slice<D: Read + Write>(
+        &mut self,
+        dev: &mut D,
+        data: &[u8],
+    ) -> ModemResult<()> {
+        self.xmodem.send_slice(dev, data)
+    }
+
+    /// See `XModemTrait::send_iter`.
+    pub fn send_iter<D: Read + Write, I: Iterator<Item = u8>>(
+        &mut self,
+        dev: &mut D,
+        iter: I,
+    ) -> ModemResult<()> {
+        self.xmodem.send_iter(dev, iter)
+    }
+
+    /// See `XModemTrait::send_broadcast`.
+    pub fn send_broadcast<D: Write, R: Read>(
+        &mut self,
+        dev: &mut D,
+        inp: &mut R,
+        checksum: ChecksumKind,
+    ) -> ModemResult<()> {
+        self.xmodem.send_broadcast(dev, inp, checksum)
+    }
+
+    /// Returns the state of the last transfer, or of the one in progress.
+    pub fn state(&self) -> &XModemState {
+        self.xmodem.state()
+    }
+
+    /// Returns the settings, e.g. to turn them into a receiver.
+    pub fn into_inner(self) -> XModem {
+        self.xmodem
+    }
+}
+
+/// An `XModem` that can only receive, made with `XModem::into_receiver`.
+///
+/// See `XModemSender` for why. The methods are those of `XModemTrait`, which
+/// documents them.
+#[derive(Debug, Copy, Clone)]
+pub struct XModemReceiver {
+    xmodem: XModem,
+}
+
+impl XModemReceiver {
+    /// Receives a transmission into `out`. See `XModemTrait::receive`.
+    pub fn receive<D: Read + Write, W: Write>(
+        &mut self,
+        dev: &mut D,
+        out: &mut W,
+        checksum: ChecksumKind,
+    ) -> ModemResult<()> {
+        self.xmodem.receive(dev, out, checksum)
+    }
+
+    /// See `XModemTrait::recv_blocks`.
+    pub fn recv_blocks<D, F>(
+        &mut self,
+        dev: &mut D,
+        checksum: ChecksumKind,
+        on_block: F,
+    ) -> ModemResult<()>
+    where
+        D: Read + Write,
+        F: FnMut(&[u8]) -> ModemResult<()>,
+    {
+        self.xmodem.recv_blocks(dev, checksum, on_block)
+    }
+
+    /// See `XModemTrait::recv_from`.
+    pub fn recv_from<D: Read + Write, W: Write>(
+        &mut self,
+        dev: &mut D,
+        out: &mut W,
+        checksum: ChecksumKind,
+        first_expected_block: u8,
+    ) -> ModemResult<()> {
+        self.xmodem
+            .recv_from(dev, out, checksum, first_expected_block)
+    }
+
+    /// See `XModemTrait::receive_hashed`.
+    pub fn receive_hashed<D: Read + Write, W: Write, H: Hasher>(
+        &mut self,
+        dev: &mut D,
+        out: &mut W,
+        checksum: ChecksumKind,
+        pad: u8,
+        hasher: &mut H,
+    ) -> ModemResult<()> {
+        self.xmodem.receive_hashed(dev, out, checksum, pad, hasher)
+    }
+
+    /// See `XModemTrait::recv_with_trailer_check`.
+    pub fn recv_with_trailer_check<D: Read + Write, W: Write>(
+        &mut self,
+        dev: &mut D,
+        out: &mut W,
+        checksum: ChecksumKind,
+        pad: u8,
+    ) -> ModemResult<()> {
+        self.xmodem.recv_with_trailer_check(dev, out, checksum, pad)
+    }
+
+    /// See `XModemTrait::recv_trim_pad`.
+    pub fn recv_trim_pad<D: Read + Write, W: Write>(
+        &mut self,
+        dev: &mut D,
+        out: &mut W,
+        checksum: ChecksumKind,
+        pad: u8,
+    ) -> ModemResult<()> {
+        self.xmodem.recv_trim_pad(dev, out, checksum, pad)
+    }
+
+    /// See `XModemTrait::recv_text`.
+    pub fn recv_text<D: Read + Write, W: Write>(
+        &mut self,
+        dev: &mut D,
+        out: &mut W,
+        checksum: ChecksumKind,
+    ) -> ModemResult<(u64, bool)> {
+        self.xmodem.recv_text(dev, out, checksum)
+    }
+
+    /// See `XModemTrait::recv_into`.
+    pub fn recv_into<D: Read + Write>(
+        &mut self,
+        dev: &mut D,
+        buf: &mut [u8],
+        checksum: ChecksumKind,
+    ) -> ModemResult<usize> {
+        self.xmodem.recv_into(dev, buf, checksum)
+    }
+
+    /// Returns the state of the last transfer, or of the one in progress.
+    pub fn state(&self) -> &XModemState {
+        self.xmodem.state()
+    }
+
+    /// Returns the settings, e.g. to turn them into a sender.
+    pub fn into_inner(self) -> XModem {
+        self.xmodem
+    }
+}
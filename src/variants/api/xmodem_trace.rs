@@ -0,0 +1,146 @@
+use core::fmt;
+use core2::io::{Read, Result, Write};
+
+/// The number of bytes a `TraceRecorder` holds by default.
+pub const DEFAULT_TRACE_CAPACITY: usize = 4096;
+
+/// Which way a byte recorded by `TraceRecorder` went.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TraceDirection {
+    /// Read from the channel.
+    Read,
+    /// Written to the channel.
+    Written,
+}
+
+/// A byte recorded by `TraceRecorder`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TraceEntry {
+    /// Which way the byte went.
+    pub direction: TraceDirection,
+    /// The byte itself.
+    pub byte: u8,
+}
+
+/// Records every byte read from and written to a channel, for attaching the
+/// raw conversation to a bug report.
+///
+/// Wrap the channel in a recorder and pass the recorder to the transfer in its
+/// place. Unlike the `event_sink`, this captures the bytes exactly as they went
+/// over the line, including noise and bytes the transfer ignored. Up to `N`
+/// bytes are kept, in a fixed array so nothing is allocated; later bytes are
+/// only counted (see `dropped`).
+///
+/// The recorder's `Display` output is a hex dump, with one line per run of
+/// bytes in the same direction (wrapped every 16 bytes): lines starting with
+/// `>` were written, and lines starting with `<` were read. The captured
+/// bytes can be fed to `XModemFrameDecoder` as well.
+pub struct TraceRecorder<'a, D, const N: usize = DEFAULT_TRACE_CAPACITY> {
+    dev: &'a mut D,
+    entries: [TraceEntry; N],
+    len: usize,
+    dropped: usize,
+}
+
+impl<'a, D, const N: usize> TraceRecorder<'a, D, N> {
+    /// Wraps `dev`, with an empty trace.
+    pub fn new(dev: &'a mut D) -> Self {
+        Self {
+            dev,
+            entries: [TraceEntry {
+                direction: TraceDirection::Read,
+                byte: 0,
+            }; N],
+            len: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Returns the bytes recorded so far, in the order they went over the
+    /// line.
+    pub fn entries(&self) -> &[TraceEntry] {
+        &self.entries[..self.len]
+    }
+
+    /// Returns the number of bytes that went over the line after the trace
+    /// filled up, which were not recorded.
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    /// Empties the trace, e.g. between transfers.
+    pub fn clear(&mut self) {
+        self.len = 0;
+        self.dropped = 0;
+    }
+
+    fn record(&mut self, direction: TraceDirection, bytes: &[u8]) {
+        for &byte in bytes {
+            if let Some(entry) = self.entries.get_mut(self.len) {
+                *entry = TraceEntry { direction, byte };
+                self.len += 1;
+            } else {
+                self.dropped += 1;
+            }
+        }
+    }
+}
+
+impl<D: Read, const N: usize> Read for TraceRecorder<'_, D, N> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let n = self.dev.read(buf)?;
+        self.record(TraceDirection::Read, &buf[..n]);
+        Ok(n)
+    }
+}
+
+impl<D: Write, const N: usize> Write for TraceRecorder<'_, D, N> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let n = self.dev.write(buf)?;
+        self.record(TraceDirection::Written, &buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.dev.flush()
+    }
+}
+
+impl<D, const N: usize> fmt::Debug for TraceRecorder<'_, D, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TraceRecorder")
+            .field("entries", &self.entries())
+            .field("dropped", &self.dropped)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<D, const N: usize> fmt::Display for TraceRecorder<'_, D, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut last = None;
+        let mut column = 0;
+        for entry in self.entries() {
+            if last != Some(entry.direction) || column == 16 {
+                if last.is_some() {
+                    writeln!(f)?;
+                }
+                let tag = match entry.direction {
+                    TraceDirection::Read => '<',
+                    TraceDirection::Written => '>',
+                };
+                write!(f, "{tag}")?;
+                last = Some(entry.direction);
+                column = 0;
+            }
+            write!(f, " {:02x}", entry.byte)?;
+            column += 1;
+        }
+        if last.is_some() {
+            writeln!(f)?;
+        }
+        if self.dropped > 0 {
+            writeln!(f, "... {} more bytes not recorded", self.dropped)?;
+        }
+        Ok(())
+    }
+}
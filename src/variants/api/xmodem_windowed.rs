@@ -0,0 +1,318 @@
+use crate::common::{
+    get_byte_timeout, purge, put_bytes, read_full, ModemError, ModemEvent,
+    ModemResult, ModemTrait, PacketNumber, XModemTrait,
+};
+use core2::io::{Read, Write};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::variants::api::xmodem::{TransferGuard, TransferHooks, XModem};
+use crate::variants::xmodem::{
+    common::{BlockLengthKind, ChecksumKind},
+    Consts,
+};
+
+/// The largest window `XModemWindowed` supports. Larger windows are clamped
+/// to this, as the unacknowledged blocks are kept on the stack.
+pub const MAX_WINDOW: u8 = 8;
+
+const FRAME_LENGTH: usize = BlockLengthKind::OneK as usize + 5;
+
+/// Pipelined XMODEM: up to `window` blocks are sent before an acknowledgement
+/// is required.
+///
+/// This is an extension of the protocol that both ends must opt into, for
+/// links where waiting for an `ACK` after every block wastes most of the
+/// bandwidth. The blocks themselves are framed exactly as by `XModem`, but
+/// every `ACK` and `NAK` from the receiver is followed by a block number:
+///
+/// - `ACK n` acknowledges every block up to and including block `n`.
+/// - `NAK n` asks the sender to resend everything from block `n` onwards.
+///
+/// The receiver only accepts blocks in order. After a `NAK` it ignores the
+/// blocks that were already in flight until the requested one arrives.
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct XModemWindowed {
+    /// The number of blocks that may be in flight without an acknowledgement.
+    /// Clamped to `1..=MAX_WINDOW`.
+    pub window: u8,
+    /// The underlying XMODEM settings, such as the block length and the
+    /// number of errors tolerated.
+    pub xmodem: XModem,
+}
+
+impl XModemWindowed {
+    /// Creates a windowed transfer with the given window and default XMODEM
+    /// settings.
+    pub fn new(window: u8) -> Self {
+        Self {
+            window,
+            xmodem: XModem::new(),
+        }
+    }
+
+    fn window(&self) -> u32 {
+        u32::from(self.window.clamp(1, MAX_WINDOW))
+    }
+
+    /// Sends the whole of `inp`.
+    pub fn send<D, R>(&mut self, dev: &mut D, inp: &mut R) -> ModemResult<()>
+    where
+        D: Read + Write,
+        R: Read,
+    {
+        self.xmodem.reset();
+
+        if self.xmodem.purge_on_start {
+            purge(dev)?;
+        }
+
+        let mut guard = TransferGuard::new(dev);
+        let dev = &mut guard;
+
+        self.xmodem.init_send(dev)?;
+
+        self.send_blocks(dev, inp)?;
+
+        self.xmodem.finish_send(dev)?;
+
+        dev.disarm();
+        Ok(())
+    }
+
+    fn send_blocks<D, R>(&mut self, dev: &mut D, inp: &mut R) -> ModemResult<()>
+    where
+        D: Read + Write,
+        R: Read,
+    {
+        let window = self.window();
+        let block_length = self.xmodem.block_length as usize;
+        let mut frames = [[0u8; FRAME_LENGTH]; MAX_WINDOW as usize];
+        let mut lengths = [0usize; MAX_WINDOW as usize];
+        let slot = |block: u32| (block % window) as usize;
+
+        let hooks = &mut TransferHooks::default();
+        let mut cancel = self.xmodem.cancel_detector();
+        let mut last_byte = None;
+        // The oldest unacknowledged block, and the next block to read.
+        let mut base = u32::from(self.xmodem.first_block);
+        let mut next = base;
+        let mut done = false;
+        // Retries of the block at `base`.
+        let mut retries = 0;
+        loop {
+            self.xmodem.yield_now(hooks);
+
+            while !done && next - base < window {
+                let buff = &mut frames[slot(next)];
+                let block = &mut buff[3..3 + block_length];
+                let n = read_full(inp, block)?;
+                if n == 0 {
+                    done = true;
+                    break;
+                }
+                self.xmodem.pad_policy.pad(block, n);
+
+                lengths[slot(next)] = self.xmodem.encode_block(buff, next, n);
+                put_bytes(dev, &buff[..lengths[slot(next)]])?;
+                self.xmodem.emit(hooks, ModemEvent::BlockSent { num: next });
+                next += 1;
+            }
+            if base == next {
+                return Ok(());
+            }
+
+            let mut timed_out = false;
+            let resend = match get_byte_timeout(dev)?.map(Consts::from) {
+                Some(c @ (Consts::ACK | Consts::NAK)) => {
+                    cancel.feed(c.into());
+                    let Some(num) = get_byte_timeout(dev)? else {
+                        self.xmodem.emit(hooks, ModemEvent::Timeout);
+                        continue;
+                    };
+                    // Responses for blocks outside the window are stale.
+                    let offset = u32::from(num.wrapping_sub(base as u8));
+                    if offset >= next - base {
+                        continue;
+                    }
+                    if c == Consts::ACK {
+                        for num in base..=base + offset {
+                            self.xmodem
+                                .emit(hooks, ModemEvent::BlockAcked { num });
+                        }
+                        base += offset + 1;
+                        retries = 0;
+                        continue;
+                    }
+                    self.xmodem.emit(hooks, ModemEvent::Nak);
+                    last_byte = Some(c.into());
+                    base += offset;
+                    true
+                }
+                Some(c) => {
+                    if cancel.feed(c.into()) {
+                        self.xmodem.emit(hooks, ModemEvent::Canceled);
+                        return Err(ModemError::Canceled);
+                    }
+                    last_byte = Some(c.into());
+                    false
+                }
+                None => {
+                    self.xmodem.emit(hooks, ModemEvent::Timeout);
+                    timed_out = true;
+                    true
+                }
+            };
+
+            if resend {
+                for num in base..next {
+                    self.xmodem.state.stats.retransmits += 1;
+                    put_bytes(dev, &frames[slot(num)][..lengths[slot(num)]])?;
+                    self.xmodem.emit(hooks, ModemEvent::BlockSent { num });
+                }
+            }
+
+            self.xmodem.count_retry(&mut retries, timed_out);
+
+            if self.xmodem.state.errors >= self.xmodem.max_errors {
+                return Err(self
+                    .xmodem
+                    .exhausted_retries(last_byte, Consts::ACK));
+            }
+        }
+    }
+
+    /// Receives a transmission into `out`, using the given checksum mode.
+    pub fn receive<D, W>(
+        &mut self,
+        dev: &mut D,
+        out: &mut W,
+        checksum: ChecksumKind,
+    ) -> ModemResult<()>
+    where
+        D: Read + Write,
+        W: Write,
+    {
+        self.xmodem.reset();
+        self.xmodem.state.checksum_mode =
+            self.xmodem.receive_checksum.unwrap_or(checksum);
+
+        if self.xmodem.purge_on_start {
+            purge(dev)?;
+        }
+
+        let mut guard = TransferGuard::new(dev);
+        let dev = &mut guard;
+
+        let poll = self.xmodem.poll_byte();
+        put_bytes(dev, &[poll])?;
+        let mut polls = 1;
+
+        let window = self.window();
+        let mut frame = [0u8; BlockLengthKind::OneK as usize + 4];
+        let mut expected = PacketNumber(self.xmodem.first_block);
+        let mut nak_sent = false;
+        let mut started = false;
+        let mut eot_seen = false;
+        let mut blocks = 0u32;
+        let mut retries = 0;
+        let mut unrecognized = 0;
+        let hooks = &mut TransferHooks::default();
+        let mut cancel = self.xmodem.cancel_detector();
+        loop {
+            self.xmodem.yield_now(hooks);
+
+            let byte = get_byte_timeout(dev)?;
+            let run = core::mem::take(&mut unrecognized);
+            if byte.is_some_and(|c| cancel.feed(c)) {
+                self.xmodem.emit(hooks, ModemEvent::Canceled);
+                return Err(ModemError::Canceled);
+            }
+            match byte.map(Consts::from) {
+                Some(c @ (Consts::SOH | Consts::STX)) => {
+                    started = true;
+                    eot_seen = false;
+                    let packet_size = self.xmodem.block_data_len(c.into());
+                    let checksum_size = self.xmodem.checksum_len();
+                    // As with `XModem`, the rest of the block is read at once.
+                    let frame = &mut frame[..2 + packet_size + checksum_size];
+                    dev.read_exact(frame)?;
+                    let (header, rest) = frame.split_at(2);
+                    let (data, sum) = rest.split_at(packet_size);
+                    let (pnum, pnum_1c) = (header[0], header[1]);
+                    let success = self.xmodem.check_block(data, sum);
+
+                    let numbered = PacketNumber::validate(pnum, pnum_1c);
+                    let behind = u32::from(expected.0.wrapping_sub(pnum));
+                    if success && numbered && pnum == expected.0 {
+                        // Dropping the guard sends the CAN sequence if either
+                        // fails, before the block is acknowledged.
+                        self.xmodem.accept_bytes(data.len(), hooks)?;
+                        out.write_all(data)?;
+                        put_bytes(dev, &[Consts::ACK.into(), pnum])?;
+                        expected = expected.next();
+                        nak_sent = false;
+                        blocks += 1;
+                        retries = 0;
+                        self.xmodem.emit(
+                            hooks,
+                            ModemEvent::BlockAcked { num: blocks },
+                        );
+                    } else if success
+                        && numbered
+                        && (1..=window).contains(&behind)
+                    {
+                        // Resent after our ACK was lost.
+                        self.xmodem.state.stats.retransmits += 1;
+                        put_bytes(dev, &[Consts::ACK.into(), pnum])?;
+                    } else if !nak_sent || (numbered && pnum == expected.0) {
+                        put_bytes(dev, &[Consts::NAK.into(), expected.0])?;
+                        self.xmodem.emit(hooks, ModemEvent::Nak);
+                        nak_sent = true;
+                        self.xmodem.count_retry(&mut retries, false);
+                    }
+                    // Otherwise the block was already in flight when we sent
+                    // our NAK, and is ignored.
+                }
+                Some(Consts::EOT) if !eot_seen => {
+                    // As with `XModem`, the first EOT is NAKed in case it
+                    // was a corrupted header.
+                    put_bytes(dev, &[Consts::NAK.into()])?;
+                    eot_seen = true;
+                }
+                Some(Consts::EOT) => {
+                    put_bytes(dev, &[Consts::ACK.into()])?;
+                    break;
+                }
+                Some(c) => {
+                    if let Consts::Unknown(c) = c {
+                        unrecognized =
+                            self.xmodem.unrecognized_byte(run, c, hooks)?;
+                    }
+                    self.xmodem.count_retry(&mut retries, false);
+                }
+                None if !started => {
+                    self.xmodem.emit(hooks, ModemEvent::Timeout);
+                    if polls >= self.xmodem.poll_count {
+                        return Err(ModemError::Timeout);
+                    }
+                    put_bytes(dev, &[poll])?;
+                    polls += 1;
+                }
+                None => {
+                    // The sender resends everything unacknowledged when it
+                    // times out, so a lost NAK is recovered from too.
+                    self.xmodem.emit(hooks, ModemEvent::Timeout);
+                    nak_sent = false;
+                    self.xmodem.count_retry(&mut retries, true);
+                }
+            }
+            if self.xmodem.state.errors >= self.xmodem.max_errors {
+                return Err(self.xmodem.too_many_errors());
+            }
+        }
+        dev.disarm();
+        Ok(())
+    }
+}
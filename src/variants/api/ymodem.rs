@@ -1,7 +1,11 @@
 use crate::common::ModemTrait;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 /// `YModem` acts as state for XMODEM transfers
-#[derive(Default, Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 #[allow(dead_code)] // TODO: Temporarily allow this lint, whilst I work out YMODEM support.
 pub struct YModem {
     /// The number of errors that can occur before the communication is
@@ -18,10 +22,50 @@ pub struct YModem {
     /// Boolean value to ignore non digits on file size.
     pub ignore_non_digits_on_file_size: bool,
 
+    #[cfg_attr(feature = "serde", serde(skip))]
+    state: YModemState,
+}
+
+/// The error counters of a YMODEM transfer, kept apart from the settings in
+/// `YModem`.
+#[derive(Default, Debug, Copy, Clone)]
+pub struct YModemState {
     errors: u32,
     initial_errors: u32,
 }
 
+impl YModemState {
+    /// Returns the number of errors counted against `max_errors`.
+    pub fn errors(&self) -> u32 {
+        self.errors
+    }
+
+    /// Returns the number of errors counted against `max_initial_errors`.
+    pub fn initial_errors(&self) -> u32 {
+        self.initial_errors
+    }
+}
+
+impl YModem {
+    /// Clears the error counters left over from the previous transfer,
+    /// keeping the settings, so that the instance can be reused.
+    pub fn reset(&mut self) {
+        self.state = YModemState::default();
+    }
+
+    /// Returns the state of the last transfer, or of the one in progress.
+    pub fn state(&self) -> &YModemState {
+        &self.state
+    }
+}
+
+// The same as `new`, so that a default instance is usable.
+impl Default for YModem {
+    fn default() -> Self {
+        <Self as ModemTrait>::new()
+    }
+}
+
 impl ModemTrait for YModem {
     fn new() -> Self
     where
@@ -31,9 +75,8 @@ impl ModemTrait for YModem {
             max_errors: 16,
             max_initial_errors: 16,
             pad_byte: 0x1a,
-            errors: 0,
-            initial_errors: 0,
             ignore_non_digits_on_file_size: false,
+            state: YModemState::default(),
         }
     }
 }
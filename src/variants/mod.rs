@@ -2,39 +2,81 @@
 
 mod api;
 
+#[cfg(feature = "xmodem")]
+pub use api::modem::{Modem, Protocol};
+
 #[cfg(feature = "xmodem")]
 pub mod xmodem {
     //! XMODEM module for XMODEM communications.
     //! Guarded by the `xmodem` feature flag.
     //! Disabled by default.
     pub(crate) use crate::common;
+    pub use crate::common::{
+        calc_crc, calc_crc32, calc_crc_ccitt, control_byte_name, purge,
+        BlockLengthKind, ChecksumKind, Crc16State, ModemError, ModemEvent,
+        ModemResult, ModemTrait, PacketNumber, PadPolicy, TransferStats,
+        XModemTrait,
+    };
     pub use crate::variants::api::xmodem::*;
+    pub use crate::variants::api::xmodem_buffered::*;
+    pub use crate::variants::api::xmodem_decoder::*;
+    #[cfg(feature = "std")]
+    pub use crate::variants::api::xmodem_file::*;
+    pub use crate::variants::api::xmodem_roles::*;
+    pub use crate::variants::api::xmodem_trace::*;
+    pub use crate::variants::api::xmodem_windowed::*;
 
-    #[derive(Default, Debug, Copy, Clone)]
-    #[repr(u8)]
+    /// Control bytes used by the protocol.
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
     #[allow(missing_docs)]
     pub enum Consts {
-        NUL = 0x00,
-        SOH = 0x01,
-        STX = 0x02,
-        EOT = 0x04,
-        ACK = 0x06,
-        ACK2 = 0x86,
-        DLE = 0x10,
-        NAK = 0x15,
-        CAN = 0x18,
-        CAN2 = 0x98,
-        CRC = 0x43,
-        CRC2 = 0xC3,
-        CRC3 = 0x83,
-        ABT = 0x61,
-        #[default]
-        Unknown = 0x99,
+        NUL,
+        SOH,
+        STX,
+        EOT,
+        ACK,
+        BS,
+        ACK2,
+        DLE,
+        NAK,
+        CAN,
+        CAN2,
+        CRC,
+        CRC2,
+        CRC3,
+        ABT,
+        /// Any byte that is not a control byte. The byte itself is kept, so
+        /// converting back to `u8` is lossless.
+        Unknown(u8),
+    }
+
+    /// `Unknown(0)`, as a placeholder that is not one of the control bytes.
+    impl Default for Consts {
+        fn default() -> Self {
+            Self::Unknown(0)
+        }
     }
 
     impl From<Consts> for u8 {
         fn from(v: Consts) -> Self {
-            v as Self
+            match v {
+                Consts::NUL => 0x00,
+                Consts::SOH => 0x01,
+                Consts::STX => 0x02,
+                Consts::EOT => 0x04,
+                Consts::ACK => 0x06,
+                Consts::BS => 0x08,
+                Consts::ACK2 => 0x86,
+                Consts::DLE => 0x10,
+                Consts::NAK => 0x15,
+                Consts::CAN => 0x18,
+                Consts::CAN2 => 0x98,
+                Consts::CRC => 0x43,
+                Consts::CRC2 => 0xC3,
+                Consts::CRC3 => 0x83,
+                Consts::ABT => 0x61,
+                Consts::Unknown(v) => v,
+            }
         }
     }
 
@@ -46,6 +88,7 @@ pub mod xmodem {
                 0x02 => Self::STX,
                 0x04 => Self::EOT,
                 0x06 => Self::ACK,
+                0x08 => Self::BS,
                 0x86 => Self::ACK2,
                 0x10 => Self::DLE,
                 0x15 => Self::NAK,
@@ -55,7 +98,27 @@ pub mod xmodem {
                 0xC3 => Self::CRC2,
                 0x83 => Self::CRC3,
                 0x61 => Self::ABT,
-                _ => Self::Unknown,
+                v => Self::Unknown(v),
+            }
+        }
+    }
+
+    impl BlockLengthKind {
+        /// Returns the block length announced by a block's header byte, or
+        /// `None` if `byte` is not a block header.
+        pub fn from_header_byte(byte: u8) -> Option<Self> {
+            match Consts::from(byte) {
+                Consts::SOH => Some(Self::Standard),
+                Consts::STX => Some(Self::OneK),
+                _ => None,
+            }
+        }
+
+        /// Returns the header byte that starts a block of this length.
+        pub fn header_byte(self) -> u8 {
+            match self {
+                Self::Standard => Consts::SOH.into(),
+                Self::OneK => Consts::STX.into(),
             }
         }
     }
@@ -66,26 +129,44 @@ pub mod ymodem {
     //! YMODEM module for YMODEM communications.
     //! Guarded by the `xmodem` feature flag.
     //! Disabled by default.
+    pub use crate::common::{ModemError, ModemResult, YModemFileInfo};
     pub use crate::variants::api::ymodem::*;
 
-    #[derive(Default, Debug, Copy, Clone)]
-    #[repr(u8)]
+    /// Control bytes used by the protocol.
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
     #[allow(missing_docs)]
     pub enum Consts {
-        SOH = 0x01,
-        STX = 0x02,
-        EOT = 0x04,
-        ACK = 0x06,
-        NAK = 0x15,
-        CAN = 0x18,
-        CRC = 0x43,
-        #[default]
-        Unknown = 0x99,
+        SOH,
+        STX,
+        EOT,
+        ACK,
+        NAK,
+        CAN,
+        CRC,
+        /// Any byte that is not a control byte. The byte itself is kept, so
+        /// converting back to `u8` is lossless.
+        Unknown(u8),
+    }
+
+    /// `Unknown(0)`, as a placeholder that is not one of the control bytes.
+    impl Default for Consts {
+        fn default() -> Self {
+            Self::Unknown(0)
+        }
     }
 
     impl From<Consts> for u8 {
         fn from(v: Consts) -> Self {
-            v as Self
+            match v {
+                Consts::SOH => 0x01,
+                Consts::STX => 0x02,
+                Consts::EOT => 0x04,
+                Consts::ACK => 0x06,
+                Consts::NAK => 0x15,
+                Consts::CAN => 0x18,
+                Consts::CRC => 0x43,
+                Consts::Unknown(v) => v,
+            }
         }
     }
 
@@ -99,7 +180,7 @@ pub mod ymodem {
                 0x15 => Self::NAK,
                 0x18 => Self::CAN,
                 0x43 => Self::CRC,
-                _ => Self::Unknown,
+                v => Self::Unknown(v),
             }
         }
     }
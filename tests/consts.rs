@@ -0,0 +1,32 @@
+//! Control bytes survive conversion to `u8` and back, and other bytes are
+//! kept as they are.
+
+use txmodems::variants::xmodem::Consts;
+
+#[test]
+fn every_byte_round_trips() {
+    for byte in 0..=u8::MAX {
+        let c = Consts::from(byte);
+        assert_eq!(u8::from(c), byte);
+        assert_eq!(Consts::from(u8::from(c)), c);
+    }
+    assert_eq!(Consts::from(0x18), Consts::CAN);
+    assert_eq!(Consts::from(0x99), Consts::Unknown(0x99));
+    assert_eq!(u8::from(Consts::Unknown(0x99)), 0x99);
+    assert_eq!(Consts::default(), Consts::Unknown(0));
+}
+
+#[cfg(feature = "ymodem")]
+#[test]
+fn every_ymodem_byte_round_trips() {
+    use txmodems::variants::ymodem::Consts;
+
+    for byte in 0..=u8::MAX {
+        let c = Consts::from(byte);
+        assert_eq!(u8::from(c), byte);
+        assert_eq!(Consts::from(u8::from(c)), c);
+    }
+    assert_eq!(Consts::from(0x43), Consts::CRC);
+    assert_eq!(Consts::from(0x99), Consts::Unknown(0x99));
+    assert_eq!(Consts::default(), Consts::Unknown(0));
+}
@@ -0,0 +1,28 @@
+//! Each control helper writes exactly its own bytes.
+
+use txmodems::control::{send_ack, send_can_sequence, send_eot, send_nak};
+use txmodems::variants::xmodem::ModemResult;
+
+/// Runs `helper` against a fixed buffer and returns what it wrote.
+fn written(helper: impl FnOnce(&mut &mut [u8]) -> ModemResult<()>) -> Vec<u8> {
+    let mut buf = [0u8; 32];
+    let mut out = &mut buf[..];
+    helper(&mut out).unwrap();
+    let len = 32 - out.len();
+    buf[..len].to_vec()
+}
+
+#[test]
+fn single_byte_helpers() {
+    assert_eq!(written(|out| send_eot(out)), [0x04]);
+    assert_eq!(written(|out| send_ack(out)), [0x06]);
+    assert_eq!(written(|out| send_nak(out)), [0x15]);
+}
+
+#[test]
+fn can_sequence_is_eight_cans_then_eight_backspaces() {
+    assert_eq!(
+        written(|out| send_can_sequence(out)),
+        [[0x18; 8], [0x08; 8]].concat()
+    );
+}
@@ -0,0 +1,28 @@
+//! The XMODEM CRCs: incremental against one-shot, and known answers.
+
+use txmodems::variants::xmodem::{calc_crc, calc_crc_ccitt, Crc16State};
+
+#[test]
+fn incremental_crc_matches_one_shot() {
+    assert_eq!(calc_crc(b"123456789"), 0x31c3);
+
+    let data: Vec<u8> = (0..1024u32).map(|i| (i * 31 % 256) as u8).collect();
+    for chunk_len in [1, 7, 128, 1000, 1024] {
+        let mut state = Crc16State::new();
+        for chunk in data.chunks(chunk_len) {
+            state.update(chunk);
+        }
+        assert_eq!(state.finalize(), calc_crc(&data));
+    }
+    assert_eq!(Crc16State::new().finalize(), calc_crc(&[]));
+}
+
+#[test]
+fn xmodem_and_ccitt_crcs_differ() {
+    // The check values of CRC-16/XMODEM and CRC-16/CCITT-FALSE.
+    assert_eq!(calc_crc(b"123456789"), 0x31c3);
+    assert_eq!(calc_crc_ccitt(b"123456789"), 0x29b1);
+    // The initial values differ, so even no data gives different CRCs.
+    assert_eq!(calc_crc(&[]), 0x0000);
+    assert_eq!(calc_crc_ccitt(&[]), 0xffff);
+}
@@ -0,0 +1,79 @@
+//! Decoding a captured XMODEM conversation with `XModemFrameDecoder`.
+
+use txmodems::variants::xmodem::{
+    calc_crc, ChecksumKind, Frame, ModemError, XModemFrameDecoder,
+};
+
+/// Frames `data`, padded to 128 bytes, as CRC block `num`.
+fn crc_block(num: u8, data: &[u8]) -> Vec<u8> {
+    let mut block = data.to_vec();
+    block.resize(128, 0x1a);
+    let mut frame = vec![0x01, num, !num];
+    frame.extend(&block);
+    frame.extend(calc_crc(&block).to_be_bytes());
+    frame
+}
+
+#[test]
+fn two_block_capture_with_a_corrupt_checksum() {
+    let mut bad = crc_block(2, b"second");
+    *bad.last_mut().unwrap() ^= 0xff;
+    // Both directions of the conversation, interleaved: block 2 is NAKed
+    // and resent.
+    let capture = [
+        b"C".as_slice(),
+        &crc_block(1, b"first"),
+        &[0x06],
+        &bad,
+        &[0x15],
+        &crc_block(2, b"second"),
+        &[0x86],
+        &[0x04, 0x06],
+    ]
+    .concat();
+
+    let frames: Vec<_> = XModemFrameDecoder::new(&capture, ChecksumKind::Crc16)
+        .collect::<Result<_, _>>()
+        .expect("capture decodes");
+    let summary: Vec<_> = frames
+        .iter()
+        .map(|frame| match *frame {
+            Frame::Data {
+                block,
+                block_ok,
+                payload,
+                checksum_ok,
+            } => {
+                assert!(block_ok);
+                assert_eq!(payload.len(), 128);
+                format!("{block}:{checksum_ok}")
+            }
+            other => format!("{other:?}"),
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            "Crc", "1:true", "Ack", "2:false", "Nak", "2:true", "Ack", "Eot",
+            "Ack"
+        ]
+    );
+}
+
+#[test]
+fn high_bit_control_bytes_are_decoded() {
+    let capture = [0x98, 0x86, 0xc3, 0x83, 0x42];
+    let frames: Vec<_> = XModemFrameDecoder::new(&capture, ChecksumKind::Crc16)
+        .map(|frame| format!("{:?}", frame.expect("decodes")))
+        .collect();
+    assert_eq!(frames, ["Can", "Ack", "Crc", "Crc", "Unknown(66)"]);
+}
+
+#[test]
+fn truncated_block_ends_the_capture() {
+    let block = crc_block(1, b"first");
+    let mut decoder =
+        XModemFrameDecoder::new(&block[..100], ChecksumKind::Crc16);
+    assert!(matches!(decoder.next(), Some(Err(ModemError::Io(_)))));
+    assert!(decoder.next().is_none());
+}
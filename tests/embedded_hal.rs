@@ -0,0 +1,77 @@
+//! XMODEM over an `embedded-hal-nb` serial port, through
+//! `EmbeddedHalSerial`.
+
+use std::collections::VecDeque;
+use std::convert::Infallible;
+
+use embedded_hal_nb::{nb, serial};
+use txmodems::embedded_hal::EmbeddedHalSerial;
+use txmodems::variants::xmodem::{
+    calc_crc, ChecksumKind, ModemError, ModemTrait, XModem, XModemTrait,
+};
+
+/// A mock serial port: reads come from `rx`, and block once it is empty;
+/// writes go to `tx`.
+#[derive(Default)]
+struct MockSerial {
+    rx: VecDeque<u8>,
+    tx: Vec<u8>,
+}
+
+impl serial::ErrorType for MockSerial {
+    type Error = Infallible;
+}
+
+impl serial::Read for MockSerial {
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        self.rx.pop_front().ok_or(nb::Error::WouldBlock)
+    }
+}
+
+impl serial::Write for MockSerial {
+    fn write(&mut self, word: u8) -> nb::Result<(), Self::Error> {
+        self.tx.push(word);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+#[test]
+fn receive_through_the_adapter() {
+    let mut block = b"Hello".to_vec();
+    block.resize(128, 0x1a);
+    let mut port = MockSerial::default();
+    // What the sender sends: the block, then EOT, repeated as the first is
+    // NAKed.
+    port.rx.extend([0x01, 1, !1]);
+    port.rx.extend(&block);
+    port.rx.extend(calc_crc(&block).to_be_bytes());
+    port.rx.extend([0x04, 0x04]);
+
+    let mut dev = EmbeddedHalSerial::new(port);
+    let mut out = Vec::new();
+    XModem::new()
+        .receive(&mut dev, &mut out, ChecksumKind::Crc16)
+        .expect("receive failed");
+    assert_eq!(out, block);
+    assert_eq!(dev.into_inner().tx, [b'C', 0x06, 0x15, 0x06]);
+}
+
+#[test]
+fn would_block_becomes_a_timeout() {
+    let mut dev = EmbeddedHalSerial::new(MockSerial::default());
+    dev.spin_budget = 10;
+    let mut receiver = XModem::new();
+    receiver.poll_count = 3;
+    let err = receiver
+        .receive(&mut dev, &mut Vec::new(), ChecksumKind::Crc16)
+        .unwrap_err();
+    assert!(matches!(err, ModemError::Timeout));
+    // One poll per timeout, then the cancel sequence.
+    let tx = dev.into_inner().tx;
+    assert_eq!(tx[..3], [b'C'; 3]);
+    assert_eq!(tx[3..], [[0x18; 8], [0x08; 8]].concat());
+}
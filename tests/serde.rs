@@ -0,0 +1,48 @@
+//! `XModem` settings survive a trip through JSON, and a partial config keeps
+//! the defaults for everything it leaves out, for `YModem` as well.
+
+use txmodems::variants::xmodem::{
+    BlockLengthKind, ChecksumKind, ModemTrait, XModem,
+};
+
+#[test]
+fn settings_round_trip_through_json() {
+    let mut xmodem = XModem::new();
+    xmodem.max_errors = 3;
+    xmodem.block_length = BlockLengthKind::OneK;
+    xmodem.receive_checksum = Some(ChecksumKind::Crc16);
+    xmodem.max_recv_bytes = Some(65536);
+
+    let json = serde_json::to_string(&xmodem).unwrap();
+    let parsed: XModem = serde_json::from_str(&json).unwrap();
+    assert_eq!(serde_json::to_string(&parsed).unwrap(), json);
+    assert_eq!(parsed.max_errors, 3);
+    assert_eq!(parsed.block_length, BlockLengthKind::OneK);
+    assert_eq!(parsed.receive_checksum, Some(ChecksumKind::Crc16));
+    assert_eq!(parsed.max_recv_bytes, Some(65536));
+}
+
+#[test]
+fn missing_settings_take_their_defaults() {
+    let parsed: XModem = serde_json::from_str(r#"{"max_errors": 5}"#).unwrap();
+    let mut expected = XModem::new();
+    expected.max_errors = 5;
+    assert_eq!(
+        serde_json::to_value(parsed).unwrap(),
+        serde_json::to_value(expected).unwrap()
+    );
+}
+
+#[cfg(feature = "ymodem")]
+#[test]
+fn missing_ymodem_settings_take_their_defaults() {
+    use txmodems::variants::ymodem::YModem;
+
+    let parsed: YModem = serde_json::from_str(r#"{"max_errors": 5}"#).unwrap();
+    let mut expected = YModem::new();
+    expected.max_errors = 5;
+    assert_eq!(
+        serde_json::to_value(parsed).unwrap(),
+        serde_json::to_value(expected).unwrap()
+    );
+}
@@ -0,0 +1,116 @@
+//! The small value types behind the protocol wrap, convert and print the way
+//! the wire format needs them to.
+
+use std::collections::BTreeMap;
+
+use txmodems::variants::xmodem::{
+    BlockLengthKind, ChecksumKind, ModemError, PacketNumber,
+};
+
+#[test]
+fn packet_number_wraps_and_complements() {
+    assert_eq!(PacketNumber(1).next(), PacketNumber(2));
+    assert_eq!(PacketNumber(255).next(), PacketNumber(0));
+    assert_eq!(PacketNumber(0).complement(), 0xff);
+    assert_eq!(PacketNumber(1).complement(), 0xfe);
+    assert_eq!(PacketNumber(255).complement(), 0x00);
+    assert!(PacketNumber::validate(255, 0x00));
+    assert!(PacketNumber::validate(0, 0xff));
+    assert!(!PacketNumber::validate(1, 0xff));
+}
+
+#[test]
+fn block_lengths_convert_from_their_size() {
+    assert_eq!(
+        BlockLengthKind::try_from(128usize).unwrap(),
+        BlockLengthKind::Standard
+    );
+    assert_eq!(
+        BlockLengthKind::try_from(1024usize).unwrap(),
+        BlockLengthKind::OneK
+    );
+    assert_eq!(
+        BlockLengthKind::try_from(128u16).unwrap(),
+        BlockLengthKind::Standard
+    );
+    assert_eq!(
+        BlockLengthKind::try_from(1024u16).unwrap(),
+        BlockLengthKind::OneK
+    );
+    assert!(matches!(
+        BlockLengthKind::try_from(256usize),
+        Err(ModemError::UnsupportedBlockLength { length: 256 })
+    ));
+    assert!(matches!(
+        BlockLengthKind::try_from(256u16),
+        Err(ModemError::UnsupportedBlockLength { length: 256 })
+    ));
+}
+
+#[test]
+fn unexpected_byte_names_both_bytes() {
+    let err = ModemError::UnexpectedByte {
+        got: 0x15,
+        expected: 0x06,
+    };
+    assert_eq!(
+        err.to_string(),
+        "Too many errors, aborting - expected ACK (0x06), got NAK (0x15)"
+    );
+}
+
+#[test]
+fn block_lengths_map_to_header_bytes() {
+    assert_eq!(BlockLengthKind::Standard.header_byte(), 0x01);
+    assert_eq!(BlockLengthKind::OneK.header_byte(), 0x02);
+    assert_eq!(
+        BlockLengthKind::from_header_byte(0x01),
+        Some(BlockLengthKind::Standard)
+    );
+    assert_eq!(
+        BlockLengthKind::from_header_byte(0x02),
+        Some(BlockLengthKind::OneK)
+    );
+    assert_eq!(BlockLengthKind::from_header_byte(0x04), None);
+    assert_eq!(BlockLengthKind::from_header_byte(0x43), None);
+}
+
+#[test]
+fn kinds_are_ordered_map_keys() {
+    let mut lengths = BTreeMap::new();
+    lengths.insert(BlockLengthKind::OneK, "1k");
+    lengths.insert(BlockLengthKind::Standard, "standard");
+    assert_eq!(
+        lengths.keys().copied().collect::<Vec<_>>(),
+        [BlockLengthKind::Standard, BlockLengthKind::OneK]
+    );
+    assert_eq!(lengths[&BlockLengthKind::OneK], "1k");
+
+    let mut checksums = BTreeMap::new();
+    checksums.insert(ChecksumKind::Crc16, 2);
+    checksums.insert(ChecksumKind::Standard, 1);
+    assert_eq!(
+        checksums.keys().copied().collect::<Vec<_>>(),
+        [ChecksumKind::Standard, ChecksumKind::Crc16]
+    );
+    assert_eq!(checksums[&ChecksumKind::Crc16], 2);
+}
+
+#[cfg(feature = "ymodem")]
+#[test]
+fn ymodem_default_is_new() {
+    use txmodems::variants::xmodem::ModemTrait;
+    use txmodems::variants::ymodem::YModem;
+
+    let default = YModem::default();
+    let new = YModem::new();
+    assert_eq!(default.max_errors, new.max_errors);
+    assert_eq!(default.max_initial_errors, new.max_initial_errors);
+    assert_eq!(default.pad_byte, new.pad_byte);
+    assert_eq!(
+        default.ignore_non_digits_on_file_size,
+        new.ignore_non_digits_on_file_size
+    );
+    assert_eq!(default.state().errors(), 0);
+    assert_eq!(default.state().initial_errors(), 0);
+}
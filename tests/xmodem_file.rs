@@ -0,0 +1,76 @@
+//! `send_file` and `recv_file` over a pseudo-terminal, standing in for a
+//! serial device.
+#![cfg(unix)]
+
+use std::fs;
+use std::path::PathBuf;
+use std::thread;
+
+use serialport::{SerialPort, TTYPort};
+use txmodems::variants::xmodem::{
+    recv_file, send_file, ChecksumKind, ModemTrait, XModem, XModemTrait,
+};
+
+/// A path in the temporary directory, unique to this test run.
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("txmodems-{}-{name}", std::process::id()))
+}
+
+/// The test data, not a whole number of blocks long.
+fn data() -> Vec<u8> {
+    (0..1000u32).map(|i| (i * 7 % 251) as u8).collect()
+}
+
+#[test]
+fn send_file_over_a_pty() {
+    let (mut master, slave) = TTYPort::pair().expect("no pty");
+    let port = slave.name().expect("pty has a name");
+    let path = temp_path("send.bin");
+    fs::write(&path, data()).unwrap();
+
+    let sender = {
+        let path = path.clone();
+        thread::spawn(move || send_file(&port, 115_200, &path))
+    };
+    let mut out = Vec::new();
+    XModem::new()
+        .receive(&mut master, &mut out, ChecksumKind::Crc16)
+        .expect("receive failed");
+    sender
+        .join()
+        .expect("sender panicked")
+        .expect("send_file failed");
+    fs::remove_file(&path).unwrap();
+    drop(slave);
+
+    assert_eq!(&out[..1000], data());
+    assert!(out[1000..].iter().all(|&b| b == 0x1a));
+}
+
+#[test]
+fn recv_file_over_a_pty() {
+    let (mut master, slave) = TTYPort::pair().expect("no pty");
+    let port = slave.name().expect("pty has a name");
+    let path = temp_path("recv.bin");
+
+    let receiver = {
+        let path = path.clone();
+        thread::spawn(move || {
+            recv_file(&port, 115_200, &path, ChecksumKind::Crc16)
+        })
+    };
+    XModem::new()
+        .send(&mut master, &mut data().as_slice())
+        .expect("send failed");
+    receiver
+        .join()
+        .expect("receiver panicked")
+        .expect("recv_file failed");
+    let out = fs::read(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    drop(slave);
+
+    // The padding of the final block is written too.
+    assert_eq!(out.len(), 1024);
+    assert_eq!(&out[..1000], data());
+}
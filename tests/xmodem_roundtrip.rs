@@ -0,0 +1,1844 @@
+//! End-to-end XMODEM transfers between a sender and a receiver on separate
+//! threads, connected by the in-memory loopback from `test_util`.
+
+use core2::io::{Read, Write};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hasher;
+use std::panic::{self, AssertUnwindSafe};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use txmodems::test_util::{
+    loopback_pair, FaultyChannel, Loopback, DEFAULT_TIMEOUT,
+};
+use txmodems::variants::xmodem::{
+    calc_crc, calc_crc32, self_test, BlockLengthKind, BufferedChannel,
+    CanDetector, ChecksumKind, ModemError, ModemEvent, ModemTrait, PadPolicy,
+    TraceRecorder, TransferHooks, XModem, XModemTrait, XModemWindowed,
+};
+use txmodems::variants::{Modem, Protocol};
+
+const PAYLOAD_LEN: usize = 4000;
+
+/// A pseudo-random payload, the same on every run.
+fn payload() -> Vec<u8> {
+    let mut state = 0x2545_f491_u32;
+    (0..PAYLOAD_LEN)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state.to_le_bytes()[0]
+        })
+        .collect()
+}
+
+/// Starts sending `data` with the given block length on another thread,
+/// flipping the written byte at `corrupt_at`, if any. Returns the receiver's
+/// end of the channel, and the sender once it is done.
+fn spawn_sender(
+    data: &[u8],
+    block_length: BlockLengthKind,
+    corrupt_at: Option<usize>,
+) -> (Loopback, JoinHandle<XModem>) {
+    let (sender_end, receiver_end) = loopback_pair();
+    let input = data.to_vec();
+    let sender = thread::spawn(move || {
+        let mut dev = FaultyChannel::new(sender_end);
+        dev.corrupt_at = corrupt_at;
+        let mut sender = XModem::new();
+        sender.block_length = block_length;
+        sender.send(&mut dev, &mut &input[..]).expect("send failed");
+        sender
+    });
+    (receiver_end, sender)
+}
+
+/// Sends `data` as `spawn_sender` does and receives it with the given
+/// checksum mode. Returns the received bytes, and the sender and receiver
+/// once they are done.
+fn roundtrip(
+    data: &[u8],
+    block_length: BlockLengthKind,
+    checksum: ChecksumKind,
+    corrupt_at: Option<usize>,
+) -> (Vec<u8>, XModem, XModem) {
+    let (mut receiver_end, sender) =
+        spawn_sender(data, block_length, corrupt_at);
+    let mut receiver = XModem::new();
+    let mut out = Vec::new();
+    receiver
+        .receive(&mut receiver_end, &mut out, checksum)
+        .expect("receive failed");
+    let sender = sender.join().expect("sender panicked");
+    (out, sender, receiver)
+}
+
+/// Receives `data` from a sender that starts `delay` after the receiver
+/// begins polling, so several polls are queued by the time it reads them.
+/// Returns the received bytes and the sender once it is done.
+fn late_sender(
+    data: &[u8],
+    checksum: ChecksumKind,
+    delay: Duration,
+) -> (Vec<u8>, XModem) {
+    let (mut sender_end, mut receiver_end) = loopback_pair();
+    let input = data.to_vec();
+    let sender = thread::spawn(move || {
+        thread::sleep(delay);
+        let mut sender = XModem::new();
+        sender
+            .send(&mut sender_end, &mut &input[..])
+            .expect("send failed");
+        sender
+    });
+    let mut out = Vec::new();
+    XModem::new()
+        .receive(&mut receiver_end, &mut out, checksum)
+        .expect("receive failed");
+    (out, sender.join().expect("sender panicked"))
+}
+
+/// Frames `data`, padded to 128 bytes, as CRC block `num`.
+fn crc_block(num: u8, data: &[u8]) -> Vec<u8> {
+    let mut block = data.to_vec();
+    block.resize(BlockLengthKind::Standard as usize, 0x1a);
+    let mut frame = vec![0x01, num, !num];
+    frame.extend(&block);
+    frame.extend(calc_crc(&block).to_be_bytes());
+    frame
+}
+
+/// Starts a receiver on another thread that polls with `poll`, then answers
+/// each block or `EOT` it reads with the next of `responses`, or with `ACK`
+/// once they run out. `crc` says whether blocks end in a 2-byte CRC rather
+/// than a checksum. Returns what it read, a frame or `EOT` at a time, once
+/// the sender goes quiet.
+fn scripted_receiver(
+    mut end: Loopback,
+    poll: u8,
+    crc: bool,
+    responses: Vec<u8>,
+) -> JoinHandle<Vec<Vec<u8>>> {
+    thread::spawn(move || {
+        end.write_all(&[poll]).unwrap();
+        let mut responses = responses.into_iter();
+        let mut read = Vec::new();
+        let mut header = [0u8];
+        while end.read_exact(&mut header).is_ok() {
+            let block_length = match header[0] {
+                0x01 => BlockLengthKind::Standard as usize,
+                0x02 => BlockLengthKind::OneK as usize,
+                _ => 0,
+            };
+            let mut message = header.to_vec();
+            if block_length > 0 {
+                let checksum_length = if crc { 2 } else { 1 };
+                message.resize(3 + block_length + checksum_length, 0);
+                end.read_exact(&mut message[1..]).unwrap();
+            }
+            read.push(message);
+            end.write_all(&[responses.next().unwrap_or(0x06)]).unwrap();
+        }
+        read
+    })
+}
+
+/// Checks that `out` is `data` followed by the padding of the final block.
+fn assert_padded(out: &[u8], data: &[u8]) {
+    assert_eq!(out.len() % BlockLengthKind::Standard as usize, 0);
+    let (received, padding) = out.split_at(data.len());
+    assert_eq!(received, data);
+    assert!(padding.iter().all(|&b| b == 0x1a));
+}
+
+#[test]
+fn roundtrip_every_mode() {
+    let data = payload();
+    for block_length in [BlockLengthKind::Standard, BlockLengthKind::OneK] {
+        for checksum in [ChecksumKind::Standard, ChecksumKind::Crc16] {
+            let (out, sender, receiver) =
+                roundtrip(&data, block_length, checksum, None);
+            assert_padded(&out, &data);
+            assert_eq!(sender.state().checksum_mode(), checksum);
+            assert_eq!(sender.state().stats().retransmits, 0);
+            assert!(!sender.state().stats().eot_unacknowledged);
+            assert_eq!(receiver.state().stats().crc_failures, 0);
+        }
+    }
+}
+
+#[test]
+fn corrupted_block_is_resent() {
+    let data = payload();
+    for block_length in [BlockLengthKind::Standard, BlockLengthKind::OneK] {
+        for checksum in [ChecksumKind::Standard, ChecksumKind::Crc16] {
+            // A data byte of the second block.
+            let frame_length = block_length as usize + 5;
+            let (out, sender, receiver) = roundtrip(
+                &data,
+                block_length,
+                checksum,
+                Some(frame_length + 50),
+            );
+            assert_padded(&out, &data);
+            assert_eq!(sender.state().stats().retransmits, 1);
+            assert_eq!(receiver.state().stats().crc_failures, 1);
+        }
+    }
+}
+
+#[test]
+fn raw_block_returns_the_response() {
+    let (sender_end, mut receiver_end) = loopback_pair();
+    let receiver = thread::spawn(move || {
+        let mut receiver = XModem::new();
+        let mut out = Vec::new();
+        receiver
+            .receive(&mut receiver_end, &mut out, ChecksumKind::Crc16)
+            .expect("receive failed");
+        out
+    });
+
+    // Flip a data byte of the second block.
+    let mut dev = FaultyChannel::new(sender_end);
+    dev.corrupt_at = Some(133 + 10);
+    let mut poll = [0u8];
+    dev.read_exact(&mut poll).unwrap();
+    assert_eq!(poll[0], b'C');
+
+    let mut sender = XModem::new();
+    let crc = ChecksumKind::Crc16;
+    let ack = sender.send_raw_block(&mut dev, 1, b"first", crc).unwrap();
+    assert_eq!(ack, 0x06);
+    let nak = sender.send_raw_block(&mut dev, 2, b"second", crc).unwrap();
+    assert_eq!(nak, 0x15);
+    let ack = sender.send_raw_block(&mut dev, 2, b"second", crc).unwrap();
+    assert_eq!(ack, 0x06);
+
+    let mut response = [0u8];
+    for expected in [0x15, 0x06] {
+        dev.write_all(&[0x04]).unwrap();
+        dev.read_exact(&mut response).unwrap();
+        assert_eq!(response[0], expected);
+    }
+    let out = receiver.join().expect("receiver panicked");
+    assert_eq!(&out[..5], b"first");
+    assert_eq!(&out[128..134], b"second");
+}
+
+#[test]
+fn duplicate_block_is_written_once() {
+    let data = payload();
+    let (receiver_end, sender) =
+        spawn_sender(&data, BlockLengthKind::Standard, None);
+    // Lose the ACK of the first block, after the receiver's poll, so the
+    // sender times out and sends it again.
+    let mut dev = FaultyChannel::new(receiver_end);
+    dev.drop_at = Some(1);
+    let mut receiver = XModem::new();
+    let mut out = Vec::new();
+    receiver
+        .receive(&mut dev, &mut out, ChecksumKind::Crc16)
+        .expect("receive failed");
+    let sender = sender.join().expect("sender panicked");
+    assert_padded(&out, &data);
+    assert_eq!(dev.faults, 1);
+    assert_eq!(sender.state().stats().retransmits, 1);
+    assert_eq!(receiver.state().stats().retransmits, 1);
+}
+
+#[test]
+fn streamed_hash_matches_the_data() {
+    let data = payload();
+    assert_ne!(data.last(), Some(&0x1a));
+    let (mut dev, sender) =
+        spawn_sender(&data, BlockLengthKind::Standard, None);
+    let mut hasher = DefaultHasher::new();
+    let mut out = Vec::new();
+    XModem::new()
+        .receive_hashed(
+            &mut dev,
+            &mut out,
+            ChecksumKind::Crc16,
+            0x1a,
+            &mut hasher,
+        )
+        .expect("receive failed");
+    sender.join().expect("sender panicked");
+
+    let mut expected = DefaultHasher::new();
+    expected.write(&data);
+    assert_eq!(out, data);
+    assert_eq!(hasher.finish(), expected.finish());
+}
+
+#[test]
+fn lost_eot_ack_still_finishes() {
+    let data = [0x55; 2 * BlockLengthKind::Standard as usize];
+    let (receiver_end, sender) =
+        spawn_sender(&data, BlockLengthKind::Standard, None);
+    // The poll, the ACKs of both blocks, the NAK of the first EOT, and then
+    // the only ACK of the second one, which is lost.
+    let mut dev = FaultyChannel::new(receiver_end);
+    dev.drop_at = Some(4);
+    let mut out = Vec::new();
+    XModem::new()
+        .receive(&mut dev, &mut out, ChecksumKind::Crc16)
+        .expect("receive failed");
+    let sender = sender.join().expect("sender panicked");
+    assert_eq!(out, data);
+    assert_eq!(dev.faults, 1);
+    assert!(sender.state().stats().eot_unacknowledged);
+}
+
+#[test]
+fn window_of_four_sends_four_blocks_before_an_ack() {
+    let data: Vec<u8> = (0..6 * 128).map(|i| (i % 251) as u8).collect();
+    let (mut dev, mut receiver_end) = loopback_pair();
+    let input = data.clone();
+    let sender = thread::spawn(move || {
+        XModemWindowed::new(4)
+            .send(&mut dev, &mut &input[..])
+            .expect("send failed");
+    });
+
+    receiver_end.write_all(b"C").unwrap();
+    let mut frames = vec![0u8; 6 * 133];
+    // All four blocks of the window arrive without an ACK.
+    receiver_end.read_exact(&mut frames[..4 * 133]).unwrap();
+    receiver_end.write_all(&[0x06, 4]).unwrap();
+    receiver_end.read_exact(&mut frames[4 * 133..]).unwrap();
+    receiver_end.write_all(&[0x06, 6]).unwrap();
+    let mut eot = [0u8];
+    receiver_end.read_exact(&mut eot).unwrap();
+    receiver_end.write_all(&[0x06]).unwrap();
+    sender.join().expect("sender panicked");
+
+    assert_eq!(eot, [0x04]);
+    for (i, frame) in frames.chunks(133).enumerate() {
+        let chunk = &data[i * 128..(i + 1) * 128];
+        assert_eq!(frame, crc_block(i as u8 + 1, chunk));
+    }
+}
+
+#[test]
+fn windowed_receive_cancels_without_acking_a_block_it_cannot_write() {
+    let (mut sender_end, mut receiver_end) = loopback_pair();
+    let receiver = thread::spawn(move || {
+        // No room for even the first block.
+        let mut out = [0u8; 0];
+        XModemWindowed::new(4).receive(
+            &mut receiver_end,
+            &mut &mut out[..],
+            ChecksumKind::Crc16,
+        )
+    });
+
+    let mut poll = [0u8];
+    sender_end.read_exact(&mut poll).unwrap();
+    assert_eq!(poll, *b"C");
+    sender_end.write_all(&crc_block(1, b"data")).unwrap();
+    let err = receiver
+        .join()
+        .expect("receiver panicked")
+        .expect_err("receive succeeded");
+    assert!(matches!(err, ModemError::Io(_)));
+
+    let mut response = vec![0u8; sender_end.bytes_to_read()];
+    sender_end.read_exact(&mut response).unwrap();
+    assert_eq!(response, [[0x18; 8], [0x08; 8]].concat());
+}
+
+#[test]
+fn recv_text_trims_final_block_only() {
+    let text = |len| (0..len).map(|i| b'a' + (i % 26) as u8);
+    // No padding: the text fills its last block.
+    let exact: Vec<u8> = text(256).collect();
+    // Partial padding, added by the sender.
+    let partial: Vec<u8> = text(200).collect();
+    // A final block of nothing but ^Zs, after a block that ends in one,
+    // which is kept.
+    let full: Vec<u8> = text(127)
+        .chain([0x1a; 1 + BlockLengthKind::Standard as usize])
+        .collect();
+
+    for (data, expected, trimmed) in [
+        (&exact, &exact[..], false),
+        (&partial, &partial[..], true),
+        (&full, &full[..128], true),
+    ] {
+        let (mut dev, sender) =
+            spawn_sender(data, BlockLengthKind::Standard, None);
+        let mut out = Vec::new();
+        let result = XModem::new()
+            .recv_text(&mut dev, &mut out, ChecksumKind::Crc16)
+            .expect("receive failed");
+        sender.join().expect("sender panicked");
+        assert_eq!(out, expected);
+        assert_eq!(result, (expected.len() as u64, trimmed));
+    }
+}
+
+#[test]
+fn sender_and_receiver_from_one_config() {
+    let data = payload();
+    let mut config = XModem::crc();
+    config.block_length = BlockLengthKind::OneK;
+
+    let (mut sender_end, mut receiver_end) = loopback_pair();
+    let mut sender = config.into_sender();
+    let input = data.clone();
+    let sender = thread::spawn(move || {
+        sender
+            .send(&mut sender_end, &mut &input[..])
+            .map(|()| sender)
+    });
+
+    let mut receiver = config.into_receiver();
+    let mut out = Vec::new();
+    receiver
+        .receive(&mut receiver_end, &mut out, ChecksumKind::Standard)
+        .expect("receive failed");
+    let sender = sender
+        .join()
+        .expect("sender panicked")
+        .expect("send failed");
+    assert_padded(&out, &data);
+    // The receiver's preset CRC mode wins over the argument.
+    assert_eq!(sender.state().checksum_mode(), ChecksumKind::Crc16);
+    assert_eq!(receiver.state().checksum_mode(), ChecksumKind::Crc16);
+}
+
+#[test]
+fn clones_of_one_config_transfer_independently() {
+    let data = payload();
+    let mut config = XModem::new();
+    config.block_length = BlockLengthKind::OneK;
+    config.max_errors = 4;
+
+    // Run both transfers at once, corrupting a block of the first only.
+    let transfers: Vec<_> = [Some(10), None]
+        .into_iter()
+        .map(|corrupt_at| {
+            let (sender_end, mut receiver_end) = loopback_pair();
+            // `XModem` is `Copy`, so each transfer gets its own copy.
+            let mut sender = config;
+            let input = data.clone();
+            let sender = thread::spawn(move || {
+                let mut dev = FaultyChannel::new(sender_end);
+                dev.corrupt_at = corrupt_at;
+                sender.send(&mut dev, &mut &input[..]).map(|()| sender)
+            });
+            let receiver = thread::spawn(move || {
+                let mut out = Vec::new();
+                XModem::new()
+                    .receive(&mut receiver_end, &mut out, ChecksumKind::Crc16)
+                    .map(|()| out)
+            });
+            (sender, receiver)
+        })
+        .collect();
+
+    let retransmits: Vec<_> = transfers
+        .into_iter()
+        .map(|(sender, receiver)| {
+            let out = receiver
+                .join()
+                .expect("receiver panicked")
+                .expect("receive failed");
+            let sender = sender
+                .join()
+                .expect("sender panicked")
+                .expect("send failed");
+            assert_padded(&out, &data);
+            assert_eq!(sender.block_length, BlockLengthKind::OneK);
+            sender.state().stats().retransmits
+        })
+        .collect();
+    assert_eq!(retransmits, [1, 0]);
+    assert_eq!(config.state().stats().retransmits, 0);
+}
+
+#[test]
+fn size_cap_cancels_a_runaway_sender() {
+    let data = payload();
+    let (mut sender_end, mut receiver_end) = loopback_pair();
+    let input = data.clone();
+    let sender = thread::spawn(move || {
+        XModem::new().send(&mut sender_end, &mut &input[..])
+    });
+
+    let mut receiver = XModem::new();
+    receiver.max_recv_bytes = Some(300);
+    let mut out = Vec::new();
+    let err = receiver
+        .receive(&mut receiver_end, &mut out, ChecksumKind::Crc16)
+        .unwrap_err();
+    assert!(matches!(err, ModemError::SizeLimitExceeded { limit: 300 }));
+    // The block that would cross the limit is never written.
+    assert_eq!(out, &data[..256]);
+    let err = sender.join().expect("sender panicked").unwrap_err();
+    assert!(matches!(err, ModemError::Canceled));
+}
+
+#[test]
+fn crc_is_sent_most_significant_byte_first() {
+    let data = b"byte order";
+    let block = crc_block(1, data);
+    let crc = calc_crc(&block[3..131]);
+    assert_ne!(crc >> 8, crc & 0xff);
+
+    // The sender puts the high byte first...
+    let (mut dev, mut receiver_end) = loopback_pair();
+    let receiver = thread::spawn(move || {
+        receiver_end.write_all(b"C").unwrap();
+        let mut frame = [0u8; 133];
+        receiver_end.read_exact(&mut frame).unwrap();
+        receiver_end.write_all(&[0x06]).unwrap();
+        let mut eot = [0u8];
+        receiver_end.read_exact(&mut eot).unwrap();
+        receiver_end.write_all(&[0x06]).unwrap();
+        frame
+    });
+    XModem::new()
+        .send_slice(&mut dev, data)
+        .expect("send failed");
+    let frame = receiver.join().expect("receiver panicked");
+    assert_eq!(frame[131..], crc.to_be_bytes());
+
+    // ...and the receiver NAKs a block with the bytes swapped.
+    let (mut dev, mut receiver_end) = loopback_pair();
+    let receiver = thread::spawn(move || {
+        let mut out = Vec::new();
+        XModem::new()
+            .receive(&mut receiver_end, &mut out, ChecksumKind::Crc16)
+            .map(|()| out)
+    });
+    let mut poll = [0u8];
+    dev.read_exact(&mut poll).unwrap();
+    let mut swapped = block.clone();
+    swapped.swap(131, 132);
+    let mut response = [0u8];
+    for (frame, expected) in [(&swapped, 0x15), (&block, 0x06)] {
+        dev.write_all(frame).unwrap();
+        dev.read_exact(&mut response).unwrap();
+        assert_eq!(response[0], expected);
+    }
+    for expected in [0x15, 0x06] {
+        dev.write_all(&[0x04]).unwrap();
+        dev.read_exact(&mut response).unwrap();
+        assert_eq!(response[0], expected);
+    }
+    let out = receiver
+        .join()
+        .expect("receiver panicked")
+        .expect("receive failed");
+    assert_eq!(&out[..data.len()], data);
+}
+
+#[test]
+fn trailer_check_accepts_only_the_right_crc32() {
+    let data = payload();
+    let crc = calc_crc32(&data);
+    for (trailer, ok) in [(crc, true), (crc ^ 1, false)] {
+        let mut input = data.clone();
+        input.extend(trailer.to_be_bytes());
+        let (mut sender_end, mut receiver_end) = loopback_pair();
+        let sender = thread::spawn(move || {
+            XModem::new().send(&mut sender_end, &mut &input[..])
+        });
+        let mut out = Vec::new();
+        let result = XModem::new().recv_with_trailer_check(
+            &mut receiver_end,
+            &mut out,
+            ChecksumKind::Crc16,
+            0x1a,
+        );
+        sender
+            .join()
+            .expect("sender panicked")
+            .expect("send failed");
+        if ok {
+            result.expect("trailer check failed");
+            assert_eq!(out, data);
+        } else {
+            let err = result.unwrap_err();
+            assert!(matches!(err, ModemError::ChecksumMismatch));
+        }
+    }
+}
+
+#[test]
+fn exhausted_retries_reports_the_count() {
+    let (mut dev, mut receiver_end) = loopback_pair();
+    // Junk instead of a start request, one byte per attempt.
+    receiver_end.write_all(b"xxxxx").unwrap();
+
+    let mut sender = XModem::new();
+    sender.max_initial_errors = 5;
+    let err = sender.send_slice(&mut dev, b"data").unwrap_err();
+    assert!(matches!(err, ModemError::ExhaustedRetries { errors: 5 }));
+    assert_eq!(err.to_string(), "Too many errors, aborting - max errors: 5");
+}
+
+#[test]
+fn abort_writes_the_cancel_sequence() {
+    let mut sink = Vec::new();
+    XModem::abort(&mut sink).expect("abort failed");
+    assert_eq!(sink, [[0x18; 8], [0x08; 8]].concat());
+}
+
+#[test]
+fn panic_mid_transfer_cancels() {
+    /// Gives one block of data, then panics.
+    struct OneBlock(bool);
+
+    impl Read for OneBlock {
+        fn read(&mut self, buf: &mut [u8]) -> core2::io::Result<usize> {
+            assert!(!self.0, "input failed");
+            self.0 = true;
+            buf.fill(0x55);
+            Ok(buf.len())
+        }
+    }
+
+    let (mut dev, mut receiver_end) = loopback_pair();
+    // A receiver that acknowledges the first block.
+    let receiver = thread::spawn(move || {
+        receiver_end.write_all(b"C").unwrap();
+        let mut frame = [0u8; 3 + BlockLengthKind::Standard as usize + 2];
+        receiver_end.read_exact(&mut frame).unwrap();
+        receiver_end.write_all(&[0x06]).unwrap();
+        let mut cancel = [0u8; 16];
+        receiver_end.read_exact(&mut cancel).unwrap();
+        (frame, cancel)
+    });
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        XModem::new().send(&mut dev, &mut OneBlock(false))
+    }));
+    assert!(result.is_err());
+
+    let (frame, cancel) = receiver.join().expect("receiver panicked");
+    assert_eq!(frame[..3], [0x01, 0x01, 0xfe]);
+    assert_eq!(cancel[..], [[0x18; 8], [0x08; 8]].concat());
+}
+
+#[test]
+fn modem_dispatches_on_protocol() {
+    let data = payload();
+    let (mut sender_end, mut receiver_end) = loopback_pair();
+    let input = data.clone();
+    let sender = thread::spawn(move || {
+        Modem::new(Protocol::Xmodem).send(
+            &mut sender_end,
+            &mut &input[..],
+            Some("payload.bin"),
+            Some(PAYLOAD_LEN as u64),
+        )
+    });
+    let mut out = Vec::new();
+    Modem::new(Protocol::Xmodem)
+        .recv(&mut receiver_end, &mut out, ChecksumKind::Crc16)
+        .expect("receive failed");
+    sender
+        .join()
+        .expect("sender panicked")
+        .expect("send failed");
+    assert_padded(&out, &data);
+
+    let mut ymodem = Modem::new(Protocol::Ymodem);
+    let err = ymodem
+        .send(&mut receiver_end, &mut &data[..], Some("payload.bin"), None)
+        .unwrap_err();
+    assert!(matches!(err, ModemError::UnsupportedProtocol));
+    let err = ymodem
+        .recv(&mut receiver_end, &mut out, ChecksumKind::Crc16)
+        .unwrap_err();
+    assert!(matches!(err, ModemError::UnsupportedProtocol));
+}
+
+#[test]
+fn hooks_can_keep_state() {
+    let data = [0x55; 2 * BlockLengthKind::Standard as usize];
+    let (mut sender_end, mut receiver_end) = loopback_pair();
+    let sender = thread::spawn(move || {
+        let mut events = Vec::new();
+        let (mut yields, mut delays) = (0, 0);
+        XModem::new()
+            .send_with_hooks(
+                &mut sender_end,
+                &mut &data[..],
+                TransferHooks {
+                    event_sink: Some(&mut |e| events.push(e)),
+                    yield_hook: Some(&mut || yields += 1),
+                    inter_block_delay: Some(&mut || delays += 1),
+                    ..TransferHooks::default()
+                },
+            )
+            .expect("send failed");
+        (events, yields, delays)
+    });
+
+    let mut received = Vec::new();
+    let mut out = Vec::new();
+    XModem::new()
+        .receive_with_hooks(
+            &mut receiver_end,
+            &mut out,
+            ChecksumKind::Crc16,
+            TransferHooks {
+                event_sink: Some(&mut |e| received.push(e)),
+                ..TransferHooks::default()
+            },
+        )
+        .expect("receive failed");
+    let (events, yields, delays) = sender.join().expect("sender panicked");
+
+    assert_eq!(out, data);
+    assert_eq!(
+        events,
+        [
+            ModemEvent::BlockSent { num: 1 },
+            ModemEvent::BlockAcked { num: 1 },
+            ModemEvent::BlockSent { num: 2 },
+            ModemEvent::BlockAcked { num: 2 },
+        ]
+    );
+    assert_eq!(
+        received,
+        [
+            ModemEvent::BlockAcked { num: 1 },
+            ModemEvent::BlockAcked { num: 2 },
+        ]
+    );
+    // Once per block, and once more to find the end of the input.
+    assert_eq!(yields, 3);
+    assert_eq!(delays, 2);
+}
+
+#[test]
+fn cancel_between_blocks_stops_the_next_one() {
+    let data = [0x55; 2 * BlockLengthKind::Standard as usize];
+    let (mut sender_end, mut receiver_end) = loopback_pair();
+    let probe = sender_end.clone();
+    // A receiver that takes the first block, then cancels.
+    let receiver = thread::spawn(move || {
+        receiver_end.write_all(b"C").unwrap();
+        let mut frame = [0u8; 3 + BlockLengthKind::Standard as usize + 2];
+        receiver_end.read_exact(&mut frame).unwrap();
+        receiver_end.write_all(&[0x06]).unwrap();
+        thread::sleep(Duration::from_millis(20));
+        receiver_end.write_all(&[0x18, 0x18]).unwrap();
+        let mut next = [0u8];
+        receiver_end.read_exact(&mut next).unwrap();
+        next[0]
+    });
+
+    let err = XModem::new()
+        .send_with_hooks(
+            &mut sender_end,
+            &mut &data[..],
+            TransferHooks {
+                inter_block_delay: Some(&mut || {
+                    thread::sleep(Duration::from_millis(60));
+                }),
+                byte_ready: Some(&mut || probe.bytes_to_read() > 0),
+                ..TransferHooks::default()
+            },
+        )
+        .unwrap_err();
+    assert!(matches!(err, ModemError::Canceled));
+    // The sender's own cancel sequence, rather than the second block.
+    assert_eq!(receiver.join().expect("receiver panicked"), 0x18);
+}
+
+#[test]
+fn stale_ack_is_not_taken_for_the_next_block() {
+    let data = [0x55; 2 * BlockLengthKind::Standard as usize];
+    let (mut sender_end, mut receiver_end) = loopback_pair();
+    // A receiver that acknowledges the first block twice.
+    let receiver = thread::spawn(move || {
+        receiver_end.write_all(b"C").unwrap();
+        let mut frame = [0u8; 3 + BlockLengthKind::Standard as usize + 2];
+        receiver_end.read_exact(&mut frame).unwrap();
+        receiver_end.write_all(&[0x06, 0x06]).unwrap();
+        receiver_end.read_exact(&mut frame).unwrap();
+        receiver_end.write_all(&[0x06]).unwrap();
+        let mut eot = [0u8];
+        receiver_end.read_exact(&mut eot).unwrap();
+        receiver_end.write_all(&[0x06]).unwrap();
+        (frame[1], eot[0])
+    });
+
+    let mut events = Vec::new();
+    XModem::new()
+        .send_with_hooks(
+            &mut sender_end,
+            &mut &data[..],
+            TransferHooks {
+                event_sink: Some(&mut |e| events.push(e)),
+                ..TransferHooks::default()
+            },
+        )
+        .expect("send failed");
+    // The second block went out, and was acknowledged, before the EOT.
+    assert_eq!(receiver.join().expect("receiver panicked"), (2, 0x04));
+    assert!(events.contains(&ModemEvent::StaleResponse { byte: 0x06 }));
+}
+
+#[test]
+fn can_detector_needs_back_to_back_cans() {
+    let feed = |bytes: &[u8]| {
+        let mut detector = CanDetector::default();
+        bytes.iter().map(|&b| detector.feed(b)).collect::<Vec<_>>()
+    };
+    assert_eq!(feed(&[0x18, 0x18]), [false, true]);
+    // `CAN2` is a `CAN` with the high bit set.
+    assert_eq!(feed(&[0x98, 0x18]), [false, true]);
+    // A burst split by noise still has two in a row.
+    assert_eq!(feed(&[0x18, 0x55, 0x18, 0x18]), [false, false, false, true]);
+    // A lone CAN, or one followed by anything else, is not enough.
+    assert_eq!(feed(&[0x18]), [false]);
+    assert_eq!(feed(&[0x18, 0x06, 0x18]), [false, false, false]);
+
+    let mut detector = CanDetector::new(3);
+    assert!(!detector.feed(0x18));
+    assert!(!detector.feed(0x18));
+    assert!(detector.feed(0x18));
+}
+
+#[test]
+fn abt_abt_cancels_receive() {
+    let (mut dev, mut sender_end) = loopback_pair();
+    let sender = thread::spawn(move || {
+        let mut byte = [0u8];
+        sender_end.read_exact(&mut byte).unwrap();
+        sender_end.write_all(&crc_block(1, b"first")).unwrap();
+        sender_end.read_exact(&mut byte).unwrap();
+        assert_eq!(byte, [0x06]);
+        // The user gives up.
+        sender_end.write_all(b"aA").unwrap();
+        let mut cancel = [0u8; 16];
+        sender_end.read_exact(&mut cancel).unwrap();
+        cancel
+    });
+
+    let mut out = Vec::new();
+    let err = XModem::new()
+        .receive(&mut dev, &mut out, ChecksumKind::Crc16)
+        .unwrap_err();
+    let cancel = sender.join().expect("sender panicked");
+    assert!(matches!(err, ModemError::Canceled));
+    assert_eq!(cancel[..], [[0x18; 8], [0x08; 8]].concat());
+}
+
+#[test]
+fn abt_in_text_does_not_cancel() {
+    let (mut dev, mut sender_end) = loopback_pair();
+    let sender = thread::spawn(move || {
+        let mut byte = [0u8];
+        sender_end.read_exact(&mut byte).unwrap();
+        sender_end.write_all(&crc_block(1, b"first")).unwrap();
+        // Stray text with `a`s in it, but never two in a row.
+        for (response, next) in [
+            (
+                0x06,
+                [b"banana".as_slice(), &crc_block(2, b"second")].concat(),
+            ),
+            (0x06, vec![0x04]),
+            (0x15, vec![0x04]),
+        ] {
+            sender_end.read_exact(&mut byte).unwrap();
+            assert_eq!(byte, [response]);
+            sender_end.write_all(&next).unwrap();
+        }
+        sender_end.read_exact(&mut byte).unwrap();
+        assert_eq!(byte, [0x06]);
+    });
+
+    let mut out = Vec::new();
+    XModem::new()
+        .receive(&mut dev, &mut out, ChecksumKind::Crc16)
+        .expect("receive failed");
+    sender.join().expect("sender panicked");
+    assert_eq!(&out[..5], b"first");
+    assert_eq!(&out[128..134], b"second");
+}
+
+#[test]
+fn stray_bytes_before_the_first_block_do_not_cancel() {
+    // The tail of a block sent before our poll, with CANs and ABTs in it, or
+    // with EOTs in it, then the first block proper.
+    for stray in [b"x\x18\x18aA\x98\x18z".as_slice(), b"x\x04yz\x04w"] {
+        let (mut dev, mut sender_end) = loopback_pair();
+        let sender = thread::spawn(move || {
+            let mut byte = [0u8];
+            sender_end.read_exact(&mut byte).unwrap();
+            sender_end
+                .write_all(&[stray, &crc_block(1, b"first")].concat())
+                .unwrap();
+            for (response, next) in [(0x06, 0x04), (0x15, 0x04)] {
+                sender_end.read_exact(&mut byte).unwrap();
+                assert_eq!(byte, [response]);
+                sender_end.write_all(&[next]).unwrap();
+            }
+            sender_end.read_exact(&mut byte).unwrap();
+            assert_eq!(byte, [0x06]);
+        });
+
+        let mut receiver = XModem::new();
+        let mut out = Vec::new();
+        receiver
+            .receive(&mut dev, &mut out, ChecksumKind::Crc16)
+            .expect("receive failed");
+        sender.join().expect("sender panicked");
+        assert_eq!(&out[..5], b"first");
+        assert_eq!(receiver.state().stats().blocks, 1);
+    }
+}
+
+#[test]
+fn only_unknown_bytes_count_towards_a_line_error() {
+    // Between the blocks, `noise` with every run of unknown bytes shorter
+    // than 4 gets through, and a run of 4 is a line error.
+    for (noise, unexpected) in [
+        (b"xy\x06xyz\x15xyz".as_slice(), None),
+        (b"xy\x06wxyz", Some(b'z')),
+    ] {
+        let (mut dev, mut sender_end) = loopback_pair();
+        let noise = noise.to_vec();
+        let sender = thread::spawn(move || {
+            let mut byte = [0u8];
+            sender_end.read_exact(&mut byte).unwrap();
+            sender_end.write_all(&crc_block(1, b"first")).unwrap();
+            sender_end.read_exact(&mut byte).unwrap();
+            assert_eq!(byte, [0x06]);
+            sender_end
+                .write_all(&[noise, crc_block(2, b"second")].concat())
+                .unwrap();
+            // The rest of the conversation, if the receiver is still there.
+            for next in [0x04, 0x04] {
+                if sender_end.read_exact(&mut byte).is_err() {
+                    break;
+                }
+                sender_end.write_all(&[next]).unwrap();
+            }
+        });
+
+        let mut receiver = XModem::new();
+        receiver.max_unrecognized = Some(4);
+        let mut out = Vec::new();
+        let result = receiver.receive(&mut dev, &mut out, ChecksumKind::Crc16);
+        sender.join().expect("sender panicked");
+        match unexpected {
+            None => {
+                result.expect("receive failed");
+                assert_eq!(&out[128..134], b"second");
+            }
+            Some(byte) => assert!(matches!(
+                result.unwrap_err(),
+                ModemError::LineError { unexpected } if unexpected == byte
+            )),
+        }
+    }
+}
+
+#[test]
+fn recv_into_fills_an_exact_buffer_and_rejects_a_small_one() {
+    let data = payload();
+    for size in [PAYLOAD_LEN, PAYLOAD_LEN - 1] {
+        let (mut sender_end, mut receiver_end) = loopback_pair();
+        let input = data.clone();
+        let sender = thread::spawn(move || {
+            XModem::new().send(&mut sender_end, &mut &input[..])
+        });
+        let mut buf = vec![0u8; size];
+        let result = XModem::new().recv_into(
+            &mut receiver_end,
+            &mut buf,
+            ChecksumKind::Crc16,
+        );
+        let sent = sender.join().expect("sender panicked");
+        if size == PAYLOAD_LEN {
+            // The padding of the final block doesn't fit, and is dropped.
+            assert_eq!(result.expect("receive failed"), PAYLOAD_LEN);
+            assert_eq!(buf, data);
+            sent.expect("send failed");
+        } else {
+            assert!(matches!(
+                result.unwrap_err(),
+                ModemError::SizeLimitExceeded { limit } if limit == size as u64
+            ));
+            assert!(matches!(sent.unwrap_err(), ModemError::Canceled));
+        }
+    }
+}
+
+#[test]
+fn first_start_request_wins() {
+    let data = [0x55; BlockLengthKind::Standard as usize];
+    let (mut dev, mut receiver_end) = loopback_pair();
+    // A receiver that asks for CRC, then falls back to the checksum before
+    // the first block arrives, and accepts whatever it gets.
+    let receiver = thread::spawn(move || {
+        receiver_end.write_all(b"C\x15").unwrap();
+        let mut frame = [0u8; 3 + BlockLengthKind::Standard as usize + 2];
+        receiver_end.read_exact(&mut frame).unwrap();
+        receiver_end.write_all(&[0x06]).unwrap();
+        let mut eot = [0u8];
+        receiver_end.read_exact(&mut eot).unwrap();
+        receiver_end.write_all(&[0x06]).unwrap();
+        (frame, eot[0])
+    });
+
+    let mut sender = XModem::new();
+    sender.send_slice(&mut dev, &data).expect("send failed");
+    let (frame, eot) = receiver.join().expect("receiver panicked");
+    assert_eq!(sender.state().checksum_mode(), ChecksumKind::Crc16);
+    assert_eq!(sender.state().stats().retransmits, 0);
+    assert_eq!(&frame[3..3 + data.len()], &data);
+    assert_eq!(eot, 0x04);
+}
+
+#[test]
+fn sender_several_timeouts_late_still_gets_through() {
+    let data = payload();
+    for checksum in [ChecksumKind::Standard, ChecksumKind::Crc16] {
+        let (out, sender) = late_sender(
+            &data,
+            checksum,
+            DEFAULT_TIMEOUT * 5 + DEFAULT_TIMEOUT / 2,
+        );
+        assert_padded(&out, &data);
+        assert_eq!(sender.state().checksum_mode(), checksum);
+    }
+}
+
+#[test]
+fn queued_polls_do_not_resend_the_first_block() {
+    let data = payload();
+    for checksum in [ChecksumKind::Crc16, ChecksumKind::Standard] {
+        // The receiver has polled four times by the time the sender starts.
+        let (out, sender) =
+            late_sender(&data, checksum, Duration::from_millis(350));
+        assert_padded(&out, &data);
+        assert_eq!(sender.state().checksum_mode(), checksum);
+        assert_eq!(sender.state().stats().retransmits, 0);
+    }
+}
+
+#[test]
+fn blocks_numbered_from_zero() {
+    let data = payload();
+    let mut config = XModem::new();
+    config.first_block = 0;
+
+    let (mut sender_end, mut receiver_end) = loopback_pair();
+    let input = data.clone();
+    let mut sender = config;
+    let sender = thread::spawn(move || {
+        let mut dev = TraceRecorder::<_, 8>::new(&mut sender_end);
+        sender.send(&mut dev, &mut &input[..]).expect("send failed");
+        // The header of the first block, after the receiver's poll.
+        dev.entries()
+            .iter()
+            .skip(1)
+            .take(3)
+            .map(|e| e.byte)
+            .collect::<Vec<_>>()
+    });
+
+    let mut receiver = config;
+    let mut out = Vec::new();
+    receiver
+        .receive(&mut receiver_end, &mut out, ChecksumKind::Crc16)
+        .expect("receive failed");
+    let header = sender.join().expect("sender panicked");
+    assert_padded(&out, &data);
+    assert_eq!(header, [0x01, 0x00, 0xff]);
+}
+
+#[test]
+fn broadcast_writes_framed_blocks_and_eot() {
+    let data: Vec<u8> = (0..130).collect();
+    let mut sink = Vec::new();
+    XModem::new()
+        .send_broadcast(&mut sink, &mut &data[..], ChecksumKind::Crc16)
+        .expect("broadcast failed");
+
+    let mut expected =
+        [crc_block(1, &data[..128]), crc_block(2, &data[128..])].concat();
+    expected.push(0x04);
+    assert_eq!(sink, expected);
+}
+
+#[test]
+fn forced_block_length_reads_1k_after_soh() {
+    let data: Vec<u8> = (0..1024u32).map(|i| (i % 251) as u8).collect();
+    let (mut dev, mut sender_end) = loopback_pair();
+    // A broken sender that starts a 1024-byte block with SOH.
+    let block = data.clone();
+    let sender = thread::spawn(move || {
+        let mut byte = [0u8];
+        sender_end.read_exact(&mut byte).unwrap();
+        assert_eq!(byte, *b"C");
+        sender_end.write_all(&[0x01, 0x01, 0xfe]).unwrap();
+        sender_end.write_all(&block).unwrap();
+        sender_end
+            .write_all(&calc_crc(&block).to_be_bytes())
+            .unwrap();
+        // The block is ACKed, then the first EOT NAKed and the second ACKed.
+        for (response, next) in
+            [(0x06, Some(0x04)), (0x15, Some(0x04)), (0x06, None)]
+        {
+            sender_end.read_exact(&mut byte).unwrap();
+            assert_eq!(byte, [response]);
+            if let Some(next) = next {
+                sender_end.write_all(&[next]).unwrap();
+            }
+        }
+    });
+
+    let mut receiver = XModem::new();
+    receiver.force_block_len = Some(BlockLengthKind::OneK);
+    let mut out = Vec::new();
+    receiver
+        .receive(&mut dev, &mut out, ChecksumKind::Crc16)
+        .expect("receive failed");
+    sender.join().expect("sender panicked");
+    assert_eq!(out, data);
+}
+
+#[test]
+fn wire_size_matches_hand_computed_values() {
+    let mut xmodem = XModem::new();
+    // Nothing to send but the EOT.
+    assert_eq!(xmodem.wire_size(0, ChecksumKind::Crc16), 1);
+    // 32 blocks of 3 + 128 + 1 or 2 bytes, then the EOT.
+    assert_eq!(xmodem.wire_size(4000, ChecksumKind::Standard), 32 * 132 + 1);
+    assert_eq!(xmodem.wire_size(4000, ChecksumKind::Crc16), 32 * 133 + 1);
+    assert_eq!(xmodem.wire_size(4096, ChecksumKind::Crc16), 32 * 133 + 1);
+
+    xmodem.block_length = BlockLengthKind::OneK;
+    // 4 blocks of 3 + 1024 + 1 or 2 bytes, the last one padded.
+    assert_eq!(xmodem.wire_size(4000, ChecksumKind::Standard), 4 * 1028 + 1);
+    assert_eq!(xmodem.wire_size(4000, ChecksumKind::Crc16), 4 * 1029 + 1);
+    // 3 full blocks, then the last 28 bytes in a 128-byte block.
+    assert_eq!(
+        xmodem.wire_size(3100, ChecksumKind::Crc16),
+        3 * 1029 + 133 + 1
+    );
+}
+
+#[test]
+fn wire_size_matches_what_is_sent() {
+    let data = payload();
+    for block_length in [BlockLengthKind::Standard, BlockLengthKind::OneK] {
+        for checksum in [ChecksumKind::Standard, ChecksumKind::Crc16] {
+            for len in [0, 1, 128, 129, 1024, 1100, PAYLOAD_LEN] {
+                let mut xmodem = XModem::new();
+                xmodem.block_length = block_length;
+                let mut sink = Vec::new();
+                xmodem
+                    .send_broadcast(&mut sink, &mut &data[..len], checksum)
+                    .expect("broadcast failed");
+                assert_eq!(
+                    xmodem.wire_size(len as u64, checksum),
+                    sink.len() as u64
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn self_test_needs_every_byte_echoed() {
+    for corrupt_at in [None, Some(40)] {
+        let (mut dev, far_end) = loopback_pair();
+        // Echo everything back until the line goes quiet, flipping the byte
+        // at `corrupt_at`.
+        let echo = thread::spawn(move || {
+            let mut far_end = FaultyChannel::new(far_end);
+            far_end.corrupt_at = corrupt_at;
+            let mut byte = [0u8];
+            while far_end.read_exact(&mut byte).is_ok() {
+                far_end.write_all(&byte).unwrap();
+            }
+        });
+        let result = self_test(&mut dev);
+        echo.join().expect("echo panicked");
+        match corrupt_at {
+            None => result.expect("self test failed"),
+            // Byte 40 is data byte 37, which is 37.
+            Some(_) => assert!(matches!(
+                result.unwrap_err(),
+                ModemError::UnexpectedByte {
+                    got: 0xda,
+                    expected: 37
+                }
+            )),
+        }
+    }
+}
+
+/// Counts the reads made of the wrapped channel.
+struct CountingChannel<T> {
+    inner: T,
+    reads: usize,
+}
+
+impl<T: Read> Read for CountingChannel<T> {
+    fn read(&mut self, buf: &mut [u8]) -> core2::io::Result<usize> {
+        self.reads += 1;
+        self.inner.read(buf)
+    }
+}
+
+impl<T: Write> Write for CountingChannel<T> {
+    fn write(&mut self, buf: &[u8]) -> core2::io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> core2::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[test]
+fn buffered_channel_reads_once_per_block() {
+    let data = payload();
+    let blocks = PAYLOAD_LEN.div_ceil(BlockLengthKind::Standard as usize);
+    for buffered in [false, true] {
+        // Not `spawn_sender`, as `FaultyChannel` writes a byte at a time.
+        let (mut sender_end, receiver_end) = loopback_pair();
+        let input = data.clone();
+        let sender = thread::spawn(move || {
+            XModem::new().send(&mut sender_end, &mut &input[..])
+        });
+        let mut dev = CountingChannel {
+            inner: receiver_end,
+            reads: 0,
+        };
+        let mut out = Vec::new();
+        let mut receiver = XModem::new();
+        if buffered {
+            receiver.receive(
+                &mut BufferedChannel::<_>::new(&mut dev),
+                &mut out,
+                ChecksumKind::Crc16,
+            )
+        } else {
+            receiver.receive(&mut dev, &mut out, ChecksumKind::Crc16)
+        }
+        .expect("receive failed");
+        sender
+            .join()
+            .expect("sender panicked")
+            .expect("send failed");
+        assert_padded(&out, &data);
+        // Every block and both EOTs arrive in a single write.
+        let per_block = if buffered { 1 } else { 2 };
+        assert_eq!(dev.reads, per_block * blocks + 2);
+    }
+}
+
+#[test]
+fn eot_that_is_never_acked_names_the_reply() {
+    let (mut dev, receiver_end) = loopback_pair();
+    // The block is acknowledged, but every EOT is NAKed.
+    let receiver = scripted_receiver(
+        receiver_end,
+        b'C',
+        true,
+        [[0x06].as_slice(), &[0x15; 32]].concat(),
+    );
+    let mut sender = XModem::new();
+    sender.max_errors = 4;
+    let err = sender.send_slice(&mut dev, b"data").unwrap_err();
+    assert!(matches!(
+        err,
+        ModemError::UnexpectedByte {
+            got: 0x15,
+            expected: 0x06
+        }
+    ));
+    // The first NAK of an EOT doesn't count as an error. The sender then
+    // cancels.
+    let read = receiver.join().expect("receiver panicked");
+    assert!(read[1..6].iter().all(|m| m == &[0x04]));
+    assert_eq!(read[6..].concat(), [[0x18; 8], [0x08; 8]].concat());
+}
+
+#[test]
+fn initial_timeouts_count_against_max_initial_errors_only() {
+    let (mut dev, receiver_end) = loopback_pair();
+    dev.inject_timeouts(20);
+    let receiver = scripted_receiver(receiver_end, b'C', true, Vec::new());
+    let mut sender = XModem::new();
+    sender.max_errors = 5;
+    sender.max_initial_errors = 30;
+    sender.send_slice(&mut dev, b"data").expect("send failed");
+    receiver.join().expect("receiver panicked");
+    assert_eq!(sender.state().initial_errors(), 20);
+    assert_eq!(sender.state().errors(), 0);
+}
+
+/// Fails every read but the third with `ErrorKind::Interrupted`, as a read
+/// cut short by a signal does.
+struct Interrupting<T> {
+    inner: T,
+    reads: u32,
+}
+
+impl<T: Read> Read for Interrupting<T> {
+    fn read(&mut self, buf: &mut [u8]) -> core2::io::Result<usize> {
+        self.reads += 1;
+        if !self.reads.is_multiple_of(3) {
+            return Err(core2::io::ErrorKind::Interrupted.into());
+        }
+        self.inner.read(buf)
+    }
+}
+
+impl<T: Write> Write for Interrupting<T> {
+    fn write(&mut self, buf: &[u8]) -> core2::io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> core2::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[test]
+fn interrupted_reads_are_retried_and_end_of_data_is_an_error() {
+    let data = payload();
+    let (mut sender_end, receiver_end) = loopback_pair();
+    let input = data.clone();
+    let sender = thread::spawn(move || {
+        XModem::new().send(&mut sender_end, &mut &input[..])
+    });
+    let mut dev = Interrupting {
+        inner: receiver_end,
+        reads: 0,
+    };
+    let mut out = Vec::new();
+    XModem::new()
+        .receive(&mut dev, &mut out, ChecksumKind::Crc16)
+        .expect("receive failed");
+    sender
+        .join()
+        .expect("sender panicked")
+        .expect("send failed");
+    assert_padded(&out, &data);
+
+    // A channel that has reached the end of its data.
+    struct Closed;
+
+    impl Read for Closed {
+        fn read(&mut self, _: &mut [u8]) -> core2::io::Result<usize> {
+            Ok(0)
+        }
+    }
+
+    impl Write for Closed {
+        fn write(&mut self, buf: &[u8]) -> core2::io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> core2::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let err = XModem::new()
+        .receive(&mut Closed, &mut Vec::new(), ChecksumKind::Crc16)
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        ModemError::Io(err) if err.kind() == core2::io::ErrorKind::UnexpectedEof
+    ));
+}
+
+#[test]
+fn resumed_send_numbers_blocks_from_the_start_block() {
+    let data = [0x55; 2 * BlockLengthKind::Standard as usize];
+    let (mut dev, receiver_end) = loopback_pair();
+    let receiver = scripted_receiver(receiver_end, b'C', true, Vec::new());
+    XModem::new()
+        .send_from(&mut dev, &mut &data[..], 3)
+        .expect("send failed");
+    let read = receiver.join().expect("receiver panicked");
+    assert_eq!(read[0][..3], [0x01, 3, !3]);
+    assert_eq!(read[1][..3], [0x01, 4, !4]);
+    assert_eq!(read[2], [0x04]);
+}
+
+#[test]
+fn block_retries_are_allowed_per_block() {
+    let data = [0x55; 2 * BlockLengthKind::Standard as usize];
+    // Each block is NAKed three times before it is acknowledged.
+    let responses = [0x15, 0x15, 0x15, 0x06].repeat(2);
+    for max_block_retries in [0, 3] {
+        let (mut dev, receiver_end) = loopback_pair();
+        let receiver =
+            scripted_receiver(receiver_end, b'C', true, responses.clone());
+        let mut sender = XModem::new();
+        sender.max_errors = 5;
+        sender.max_block_retries = max_block_retries;
+        let result = sender.send_slice(&mut dev, &data);
+        receiver.join().expect("receiver panicked");
+        if max_block_retries == 0 {
+            // Six retries in all.
+            assert!(result.is_err());
+        } else {
+            result.expect("send failed");
+            assert_eq!(sender.state().errors(), 0);
+            assert_eq!(sender.state().stats().retransmits, 6);
+        }
+    }
+}
+
+#[test]
+fn first_eot_is_naked_and_data_may_follow_it() {
+    let (mut dev, mut sender_end) = loopback_pair();
+    // An EOT after the first block, which the receiver NAKs as it may be a
+    // corrupted header, then the second block and the real end.
+    let sender = thread::spawn(move || {
+        let mut byte = [0u8];
+        sender_end.read_exact(&mut byte).unwrap();
+        let mut responses = Vec::new();
+        for message in [
+            crc_block(1, b"first"),
+            vec![0x04],
+            crc_block(2, b"second"),
+            vec![0x04],
+            vec![0x04],
+        ] {
+            sender_end.write_all(&message).unwrap();
+            sender_end.read_exact(&mut byte).unwrap();
+            responses.push(byte[0]);
+        }
+        responses
+    });
+
+    let mut out = Vec::new();
+    XModem::new()
+        .receive(&mut dev, &mut out, ChecksumKind::Crc16)
+        .expect("receive failed");
+    let responses = sender.join().expect("sender panicked");
+    assert_eq!(responses, [0x06, 0x15, 0x06, 0x15, 0x06]);
+    assert_eq!(&out[..5], b"first");
+    assert_eq!(&out[128..134], b"second");
+    assert_eq!(out.len(), 256);
+}
+
+#[test]
+fn only_timeouts_give_timeout() {
+    // Nobody sends.
+    let (mut dev, _other_end) = loopback_pair();
+    let mut receiver = XModem::new();
+    receiver.poll_count = 2;
+    let err = receiver
+        .receive(&mut dev, &mut Vec::new(), ChecksumKind::Crc16)
+        .unwrap_err();
+    assert!(matches!(err, ModemError::Timeout));
+
+    // Nobody asks to start.
+    let (mut dev, _other_end) = loopback_pair();
+    let mut sender = XModem::new();
+    sender.max_initial_errors = 2;
+    let err = sender.send_slice(&mut dev, b"data").unwrap_err();
+    assert!(matches!(err, ModemError::Timeout));
+
+    // The receiver goes quiet after asking to start.
+    let (mut dev, mut receiver_end) = loopback_pair();
+    receiver_end.write_all(b"C").unwrap();
+    let mut sender = XModem::new();
+    sender.max_errors = 2;
+    let err = sender.send_slice(&mut dev, b"data").unwrap_err();
+    assert!(matches!(err, ModemError::Timeout));
+}
+
+#[test]
+fn short_reads_of_the_input_still_fill_blocks() {
+    /// Returns at most 7 bytes per read.
+    struct Chunked<'a>(&'a [u8]);
+
+    impl Read for Chunked<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> core2::io::Result<usize> {
+            let n = buf.len().min(7).min(self.0.len());
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    let data = payload();
+    let (mut sender_end, mut receiver_end) = loopback_pair();
+    let input = data.clone();
+    let sender = thread::spawn(move || {
+        let mut sender = XModem::new();
+        sender
+            .send(&mut sender_end, &mut Chunked(&input))
+            .expect("send failed");
+        sender
+    });
+    let mut out = Vec::new();
+    XModem::new()
+        .receive(&mut receiver_end, &mut out, ChecksumKind::Crc16)
+        .expect("receive failed");
+    let sender = sender.join().expect("sender panicked");
+    assert_padded(&out, &data);
+    assert_eq!(
+        sender.state().stats().blocks as usize,
+        PAYLOAD_LEN.div_ceil(BlockLengthKind::Standard as usize)
+    );
+}
+
+#[test]
+fn init_byte_override_is_sent_first() {
+    let (mut dev, mut sender_end) = loopback_pair();
+    let sender = thread::spawn(move || {
+        let mut poll = [0u8];
+        sender_end.read_exact(&mut poll).unwrap();
+        let mut byte = [0u8];
+        for message in [crc_block(1, b"data"), vec![0x04], vec![0x04]] {
+            sender_end.write_all(&message).unwrap();
+            sender_end.read_exact(&mut byte).unwrap();
+        }
+        poll[0]
+    });
+
+    let mut receiver = XModem::new();
+    receiver.init_byte_override = Some(b'G');
+    let mut out = Vec::new();
+    receiver
+        .receive(&mut dev, &mut out, ChecksumKind::Crc16)
+        .expect("receive failed");
+    assert_eq!(sender.join().expect("sender panicked"), b'G');
+    assert_eq!(&out[..4], b"data");
+}
+
+#[test]
+fn recv_from_skips_to_the_resume_block() {
+    let data = payload();
+    let skipped = 2 * BlockLengthKind::Standard as usize;
+    // A sender that resumes at block 3, and one that starts over.
+    for start_block in [3, 1] {
+        let (mut sender_end, mut receiver_end) = loopback_pair();
+        let input = if start_block == 3 {
+            data[skipped..].to_vec()
+        } else {
+            data.clone()
+        };
+        let sender = thread::spawn(move || {
+            XModem::new().send_from(
+                &mut sender_end,
+                &mut &input[..],
+                start_block,
+            )
+        });
+        let mut out = Vec::new();
+        XModem::new()
+            .recv_from(&mut receiver_end, &mut out, ChecksumKind::Crc16, 3)
+            .expect("receive failed");
+        sender
+            .join()
+            .expect("sender panicked")
+            .expect("send failed");
+        // The first two blocks are dropped either way.
+        assert_padded(&out, &data[skipped..]);
+    }
+}
+
+#[test]
+fn supports_resume_follows_the_setting() {
+    let mut xmodem = XModem::new();
+    assert!(xmodem.supports_resume());
+    xmodem.resumable = false;
+    assert!(!xmodem.supports_resume());
+    assert!(XModem::crc().supports_resume());
+}
+
+#[test]
+fn crc_request_with_the_high_bit_set_selects_crc() {
+    let data = payload();
+    let (mut sender_end, mut receiver_end) = loopback_pair();
+    let input = data.clone();
+    let sender = thread::spawn(move || {
+        let mut sender = XModem::new();
+        sender
+            .send(&mut sender_end, &mut &input[..])
+            .expect("send failed");
+        sender
+    });
+    let mut receiver = XModem::new();
+    receiver.init_byte_override = Some(0xc3);
+    let mut out = Vec::new();
+    receiver
+        .receive(&mut receiver_end, &mut out, ChecksumKind::Crc16)
+        .expect("receive failed");
+    let sender = sender.join().expect("sender panicked");
+    assert_padded(&out, &data);
+    assert_eq!(sender.state().checksum_mode(), ChecksumKind::Crc16);
+}
+
+#[test]
+fn retries_of_several_blocks_are_logged() {
+    let data = [0x55; 4 * BlockLengthKind::Standard as usize];
+    let (mut dev, receiver_end) = loopback_pair();
+    // Block 2 is NAKed once and block 4 twice.
+    let responses = vec![0x06, 0x15, 0x06, 0x06, 0x15, 0x15, 0x06];
+    let receiver = scripted_receiver(receiver_end, b'C', true, responses);
+    let mut sender = XModem::new();
+    sender.send_slice(&mut dev, &data).expect("send failed");
+    receiver.join().expect("receiver panicked");
+    assert_eq!(sender.state().recent_retries(), [(2, 1), (4, 2)]);
+}
+
+/// A nonstandard CRC: the XMODEM one, inverted.
+fn inverted_crc(data: &[u8]) -> u16 {
+    !calc_crc(data)
+}
+
+#[test]
+fn custom_crc_is_used_on_both_ends() {
+    let data = payload();
+    let mut config = XModem::new();
+    config.crc_fn = Some(inverted_crc);
+
+    // On the wire.
+    let (mut dev, receiver_end) = loopback_pair();
+    let receiver = scripted_receiver(receiver_end, b'C', true, Vec::new());
+    let mut sender = config;
+    sender.send_slice(&mut dev, b"data").expect("send failed");
+    let frame = &receiver.join().expect("receiver panicked")[0];
+    let (block, crc) = frame[3..].split_at(BlockLengthKind::Standard as usize);
+    assert_eq!(crc, inverted_crc(block).to_be_bytes());
+
+    // And between two ends that agree on it.
+    let (mut sender_end, mut receiver_end) = loopback_pair();
+    let input = data.clone();
+    let mut sender = config;
+    let sender =
+        thread::spawn(move || sender.send(&mut sender_end, &mut &input[..]));
+    let mut receiver = config;
+    let mut out = Vec::new();
+    receiver
+        .receive(&mut receiver_end, &mut out, ChecksumKind::Crc16)
+        .expect("receive failed");
+    sender
+        .join()
+        .expect("sender panicked")
+        .expect("send failed");
+    assert_padded(&out, &data);
+    assert_eq!(receiver.state().stats().crc_failures, 0);
+}
+
+/// Records what was written between each pair of flushes.
+struct FlushRecorder<T> {
+    inner: T,
+    pending: Vec<u8>,
+    flushed: Vec<Vec<u8>>,
+}
+
+impl<T: Read> Read for FlushRecorder<T> {
+    fn read(&mut self, buf: &mut [u8]) -> core2::io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl<T: Write> Write for FlushRecorder<T> {
+    fn write(&mut self, buf: &[u8]) -> core2::io::Result<usize> {
+        self.pending.extend(buf);
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> core2::io::Result<()> {
+        self.flushed.push(std::mem::take(&mut self.pending));
+        self.inner.flush()
+    }
+}
+
+#[test]
+fn every_frame_is_flushed() {
+    let data = [0x55; 2 * BlockLengthKind::Standard as usize];
+    let (sender_end, receiver_end) = loopback_pair();
+    let sender = thread::spawn(move || {
+        let mut dev = FlushRecorder {
+            inner: sender_end,
+            pending: Vec::new(),
+            flushed: Vec::new(),
+        };
+        XModem::new()
+            .send_slice(&mut dev, &data)
+            .expect("send failed");
+        dev
+    });
+    let mut dev = FlushRecorder {
+        inner: receiver_end,
+        pending: Vec::new(),
+        flushed: Vec::new(),
+    };
+    XModem::new()
+        .receive(&mut dev, &mut Vec::new(), ChecksumKind::Crc16)
+        .expect("receive failed");
+    let sender = sender.join().expect("sender panicked");
+
+    let frame = 3 + BlockLengthKind::Standard as usize + 2;
+    let sent: Vec<_> = sender.flushed.iter().map(Vec::len).collect();
+    assert_eq!(sent, [frame, frame, 1, 1]);
+    assert!(sender.pending.is_empty());
+    assert_eq!(dev.flushed, [[b'C'], [0x06], [0x06], [0x15], [0x06]]);
+    assert!(dev.pending.is_empty());
+}
+
+#[test]
+fn pad_policies_fill_the_last_block() {
+    let data = [0x55; BlockLengthKind::Standard as usize + 2];
+    let mut byte = XModem::new();
+    byte.pad_policy = PadPolicy::Byte(0x00);
+    let mut ctrl_z = XModem::new();
+    ctrl_z.pad_policy = PadPolicy::CtrlZTerminated;
+    // The setter that replaced the `pad_byte` field.
+    let mut legacy = XModem::new();
+    #[allow(deprecated)]
+    legacy.set_pad_byte(0xff);
+    #[allow(deprecated)]
+    let legacy_byte = legacy.pad_byte();
+    assert_eq!(legacy_byte, 0xff);
+
+    let zeroes = [0u8; 126];
+    let mut terminated = zeroes;
+    terminated[0] = 0x1a;
+    for (mut sender, padding) in
+        [(byte, zeroes), (ctrl_z, terminated), (legacy, [0xff; 126])]
+    {
+        let (mut dev, receiver_end) = loopback_pair();
+        let receiver = scripted_receiver(receiver_end, b'C', true, Vec::new());
+        sender.send_slice(&mut dev, &data).expect("send failed");
+        let read = receiver.join().expect("receiver panicked");
+        // Past the header: the last two bytes of data, then the padding.
+        let last = &read[1][3..3 + BlockLengthKind::Standard as usize];
+        assert_eq!(last[..2], [0x55, 0x55]);
+        assert_eq!(last[2..], padding);
+    }
+}
+
+#[test]
+fn send_with_buffer_reuses_one_buffer() {
+    let data = payload();
+    let mut buf = vec![0u8; BlockLengthKind::OneK as usize + 5];
+    for block_length in [BlockLengthKind::Standard, BlockLengthKind::OneK] {
+        let (mut sender_end, mut receiver_end) = loopback_pair();
+        let receiver = thread::spawn(move || {
+            let mut out = Vec::new();
+            XModem::new()
+                .receive(&mut receiver_end, &mut out, ChecksumKind::Crc16)
+                .expect("receive failed");
+            out
+        });
+        let mut sender = XModem::new();
+        sender.block_length = block_length;
+        sender
+            .send_with_buffer(&mut sender_end, &mut &data[..], &mut buf)
+            .expect("send failed");
+        assert_padded(&receiver.join().expect("receiver panicked"), &data);
+        assert!(sender.state().stats().blocks > 1);
+    }
+}
+
+#[test]
+fn one_k_sends_a_short_tail_as_a_standard_block() {
+    let data = &payload()[..1100];
+    let (mut sender_end, receiver_end) = loopback_pair();
+    let receiver = scripted_receiver(receiver_end, b'C', true, Vec::new());
+    let mut sender = XModem::new();
+    sender.block_length = BlockLengthKind::OneK;
+    sender
+        .send_slice(&mut sender_end, data)
+        .expect("send failed");
+
+    let read = receiver.join().expect("receiver panicked");
+    let headers: Vec<u8> = read.iter().map(|message| message[0]).collect();
+    assert_eq!(headers, [0x02, 0x01, 0x04]);
+    assert_eq!(&read[0][3..1027], &data[..1024]);
+    assert_eq!(&read[1][3..79], &data[1024..]);
+    assert!(read[1][79..131].iter().all(|&b| b == 0x1a));
+}
+
+#[test]
+fn purge_on_start_drops_leftover_junk() {
+    let data = payload();
+    let (mut sender_end, mut receiver_end) = loopback_pair();
+    // What an aborted transfer might leave behind: part of a block and an EOT.
+    sender_end
+        .write_all(&[0x01, 0x05, 0xfa, 0x55, 0xaa, 0x04, 0x18])
+        .unwrap();
+    assert_eq!(receiver_end.bytes_to_read(), 7);
+
+    let input = data.clone();
+    let sender = thread::spawn(move || {
+        XModem::new()
+            .send(&mut sender_end, &mut &input[..])
+            .expect("send failed");
+    });
+    let mut receiver = XModem::new();
+    receiver.purge_on_start = true;
+    let mut out = Vec::new();
+    receiver
+        .receive(&mut receiver_end, &mut out, ChecksumKind::Crc16)
+        .expect("receive failed");
+    sender.join().expect("sender panicked");
+    assert_padded(&out, &data);
+    assert_eq!(receiver.state().errors(), 0);
+}
+
+#[test]
+fn second_transfer_starts_with_clean_state() {
+    let data = payload();
+    let mut receiver = XModem::new();
+
+    // A data byte of the second block.
+    let (mut end, sender) =
+        spawn_sender(&data, BlockLengthKind::Standard, Some(133 + 50));
+    let mut out = Vec::new();
+    receiver
+        .receive(&mut end, &mut out, ChecksumKind::Crc16)
+        .expect("receive failed");
+    sender.join().expect("sender panicked");
+    assert_padded(&out, &data);
+    assert!(receiver.state().errors() > 0);
+    assert_eq!(receiver.state().stats().crc_failures, 1);
+
+    let (mut end, sender) =
+        spawn_sender(&data, BlockLengthKind::Standard, None);
+    let mut out = Vec::new();
+    receiver
+        .receive(&mut end, &mut out, ChecksumKind::Crc16)
+        .expect("receive failed");
+    sender.join().expect("sender panicked");
+    assert_padded(&out, &data);
+    assert_eq!(receiver.state().errors(), 0);
+    assert_eq!(receiver.state().initial_errors(), 0);
+    assert_eq!(receiver.state().stats().crc_failures, 0);
+}
+
+#[test]
+fn send_iter_sends_what_the_iterator_yields() {
+    let (mut sender_end, mut receiver_end) = loopback_pair();
+    let receiver = thread::spawn(move || {
+        let mut out = Vec::new();
+        XModem::new()
+            .receive(&mut receiver_end, &mut out, ChecksumKind::Crc16)
+            .expect("receive failed");
+        out
+    });
+    XModem::new()
+        .send_iter(&mut sender_end, 0u8..=200)
+        .expect("send failed");
+    let expected: Vec<u8> = (0u8..=200).collect();
+    assert_padded(&receiver.join().expect("receiver panicked"), &expected);
+}
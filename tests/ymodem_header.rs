@@ -0,0 +1,86 @@
+//! Decoding the name and size from a YMODEM header block.
+
+use txmodems::variants::ymodem::{ModemError, YModemFileInfo};
+
+/// A header block as sent: the fields, padded with NULs to 128 bytes.
+fn block(fields: &[u8]) -> Vec<u8> {
+    let mut block = fields.to_vec();
+    block.resize(128, 0);
+    block
+}
+
+#[test]
+fn name_and_size_are_decoded() {
+    let info = YModemFileInfo::from_header(&block(b"foo.bin\x001234"), false)
+        .expect("valid header");
+    assert_eq!(info.file_name, "foo.bin");
+    assert_eq!(info.file_size, 1234);
+
+    // Fields after the size, such as the modification time, are ignored.
+    let info = YModemFileInfo::from_header(
+        &block(b"foo.bin\x001234 14751025674 100644"),
+        false,
+    )
+    .expect("valid header");
+    assert_eq!(info.file_size, 1234);
+}
+
+#[test]
+fn bad_size_fields_are_invalid() {
+    for fields in [
+        // An empty size field.
+        b"foo.bin\x00".as_slice(),
+        b"foo.bin\x00 14751025674",
+        // Not a number.
+        b"foo.bin\x00abc",
+    ] {
+        assert!(matches!(
+            YModemFileInfo::from_header(&block(fields), false),
+            Err(ModemError::InvalidHeader)
+        ));
+    }
+    // No NUL after the name, so there is no size field at all.
+    assert!(matches!(
+        YModemFileInfo::from_header(&[b'x'; 128], false),
+        Err(ModemError::InvalidHeader)
+    ));
+}
+
+#[test]
+fn non_digits_can_be_skipped() {
+    let fields = block(b"foo.bin\x0012k34");
+    assert!(matches!(
+        YModemFileInfo::from_header(&fields, false),
+        Err(ModemError::InvalidHeader)
+    ));
+    let info =
+        YModemFileInfo::from_header(&fields, true).expect("valid header");
+    assert_eq!(info.file_size, 1234);
+    // Still invalid without a single digit.
+    assert!(matches!(
+        YModemFileInfo::from_header(&block(b"foo.bin\x00abc"), true),
+        Err(ModemError::InvalidHeader)
+    ));
+}
+
+#[test]
+fn name_cut_mid_character_is_still_decoded() {
+    // A sender that truncated "...é" to 32 bytes, keeping only the first
+    // byte of the two-byte character.
+    let mut fields = vec![b'a'; 31];
+    fields.extend("é".as_bytes());
+    fields.truncate(32);
+    fields.extend(b"\x0042");
+    let info =
+        YModemFileInfo::from_header(&block(&fields), false).expect("valid");
+    assert_eq!(info.file_name, format!("{}\u{fffd}", "a".repeat(31)));
+    assert_eq!(info.file_size, 42);
+
+    // The whole character is kept when it fits.
+    let mut fields = vec![b'a'; 30];
+    fields.extend("é".as_bytes());
+    fields.extend(b"\x0042");
+    let info =
+        YModemFileInfo::from_header(&block(&fields), false).expect("valid");
+    assert_eq!(info.file_name, format!("{}é", "a".repeat(30)));
+}